@@ -0,0 +1,152 @@
+#![forbid(unsafe_code)]
+#![warn(clippy::all, rust_2018_idioms)]
+
+use std::{fs::File, io::BufWriter, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
+use rays_core::{
+    framebuffer::Framebuffer,
+    image::{self, ImageError, ImageFormat},
+    job::RenderOutcome,
+    sampler,
+    tonemap::{self, DisplayTransform},
+    PathTracer, RenderSettings, Scene,
+};
+
+/// Renders a scene without a window, and writes the result to disk.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Scene file to render. The built-in demo scene is rendered when omitted.
+    scene: Option<PathBuf>,
+    /// Width of the rendered image in pixels.
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+    /// Height of the rendered image in pixels.
+    #[arg(long, default_value_t = 600)]
+    height: u32,
+    /// Number of samples traced per pixel.
+    #[arg(short, long, default_value_t = 32)]
+    samples: usize,
+    /// Maximum number of rays in a path. Without it, paths are only ended by Russian roulette.
+    #[arg(short = 'b', long)]
+    max_bounces: Option<u32>,
+    /// Seed of the random sampling. Renders with the same seed and settings are identical.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Sample sequence: independent, stratified, halton, sobol or "blue noise".
+    #[arg(long, default_value = "sobol")]
+    sampler: String,
+    /// Stop sampling pixels once the standard error of their mean is under this fraction of it,
+    /// and spend the saved samples on noisier pixels, keeping the same average sample count.
+    #[arg(long)]
+    adaptive_threshold: Option<f32>,
+    /// Path the finished image is written to. The format is picked from the extension, which can
+    /// be `png`, `ppm` or `pfm`.
+    #[arg(short, long, default_value = "render.png")]
+    output: PathBuf,
+    /// Also write a PNG heatmap of the number of samples taken for each pixel to this path.
+    #[arg(long)]
+    heatmap: Option<PathBuf>,
+    /// Tone mapping operator used for png and ppm output: clamp, reinhard, "extended reinhard",
+    /// "aces filmic" or agx.
+    #[arg(long, default_value = "aces filmic")]
+    tone_mapper: String,
+    /// Exposure adjustment in stops, applied before tone mapping.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f32,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.width == 0 || args.height == 0 {
+        eprintln!("error: the resolution must be at least 1x1");
+        return ExitCode::FAILURE;
+    }
+
+    if ImageFormat::from_path(&args.output).is_none() {
+        eprintln!("error: {}", ImageError::UnknownFormat);
+        return ExitCode::FAILURE;
+    }
+
+    let Some(tone_mapper) = tonemap::by_name(&args.tone_mapper) else {
+        eprintln!("error: unknown tone mapper \"{}\"", args.tone_mapper);
+        return ExitCode::FAILURE;
+    };
+    let Some(sampler) = sampler::by_name(&args.sampler) else {
+        eprintln!("error: unknown sampler \"{}\"", args.sampler);
+        return ExitCode::FAILURE;
+    };
+    let display = DisplayTransform {
+        exposure: args.exposure,
+        tone_mapper,
+    };
+
+    let aspect_ratio = args.width as f32 / args.height as f32;
+    let scene = match &args.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(mut scene) => {
+                scene.camera.set_aspect_ratio(aspect_ratio);
+                scene
+            }
+            Err(e) => {
+                eprintln!("error: could not load {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Scene::demo(aspect_ratio),
+    };
+    let settings = RenderSettings {
+        samples: args.samples,
+        max_bounces: args.max_bounces,
+        seed: args.seed,
+        sampler,
+        adaptive_threshold: args.adaptive_threshold,
+        ..Default::default()
+    };
+    let job = PathTracer::build([args.width, args.height]).run(scene, settings);
+
+    let progress = ProgressBar::new(args.width as u64 * args.height as u64 * args.samples as u64);
+    progress.set_style(
+        ProgressStyle::with_template("{elapsed_precise} [{wide_bar}] {percent}% eta {eta}")
+            .expect("progress template is valid"),
+    );
+
+    let mut framebuffer = Framebuffer::new([args.width, args.height]);
+    for tile in job.receiver().iter() {
+        framebuffer.add(&tile);
+        progress.inc(tile.pixels.iter().map(|pixel| pixel.traced as u64).sum());
+    }
+
+    match job.join() {
+        // Adaptive renders trace a different number of samples than the bar expects
+        RenderOutcome::Completed => progress.finish(),
+        outcome => {
+            progress.abandon();
+            eprintln!("error: render stopped early ({outcome:?})");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Err(e) = image::save(&args.output, &framebuffer, &display) {
+        eprintln!("error: could not write {}: {e}", args.output.display());
+        return ExitCode::FAILURE;
+    }
+    if let Some(path) = &args.heatmap {
+        let result = File::create(path)
+            .map_err(ImageError::from)
+            .and_then(|file| {
+                image::write_png(
+                    BufWriter::new(file),
+                    framebuffer.size(),
+                    &framebuffer.heatmap_rgba8(),
+                )
+            });
+        if let Err(e) = result {
+            eprintln!("error: could not write {}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
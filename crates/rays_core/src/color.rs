@@ -1,8 +1,8 @@
 use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Sub};
 
-use glam::{Vec4, Vec4Swizzles};
+use glam::{Vec3, Vec4, Vec4Swizzles};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Color {
     pub(crate) inner: Vec4,
 }
@@ -29,6 +29,10 @@ impl Color {
         self.inner.w
     }
     #[inline(always)]
+    pub fn rgb(&self) -> Vec3 {
+        self.inner.xyz()
+    }
+    #[inline(always)]
     pub fn into_bytes(&self) -> [u8; 4] {
         [
             (self.r().min(1.0) * 255.0) as u8,
@@ -149,3 +153,9 @@ where
         }
     }
 }
+
+/// Relative luminance of a linear sRGB color.
+#[inline(always)]
+pub fn luminance(rgb: Vec3) -> f32 {
+    rgb.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
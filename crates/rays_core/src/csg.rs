@@ -0,0 +1,348 @@
+//! Constructive solid geometry, combining two surfaces into one.
+//!
+//! Every combinator has a smooth variant, which rounds the seam between the surfaces over a blend
+//! radius using the polynomial smooth minimum from
+//! [Inigo Quilez](https://iquilezles.org/articles/smin/). Its normal is the gradient of the
+//! combined distance, which is a weighted sum of the gradients of both surfaces.
+
+use glam::Vec3A;
+
+use crate::{scene::ShapeDescriptor, BoundingSphere, Sdf};
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec3A};
+
+    use crate::{
+        csg::{Intersection, Subtraction, Union, Xor},
+        sdf::{Cuboid, Sphere, Torus},
+        Sdf,
+    };
+
+    fn pairs() -> Vec<(Box<dyn Sdf>, Box<dyn Sdf>)> {
+        vec![
+            (
+                Box::new(Sphere::new(Vec3::new(-0.3, 0.0, 0.0), 0.8)),
+                Box::new(Cuboid::new(Vec3::new(0.4, 0.2, 0.0), Vec3::splat(0.6))),
+            ),
+            (
+                Box::new(Torus::new(Vec3::ZERO, 0.8, 0.3)),
+                Box::new(Sphere::new(Vec3::new(0.8, 0.0, 0.0), 0.5)),
+            ),
+        ]
+    }
+
+    fn combinations() -> Vec<Box<dyn Sdf>> {
+        let mut combinations: Vec<Box<dyn Sdf>> = Vec::new();
+        for blend in [0.0, 0.3] {
+            for (a, b) in pairs() {
+                combinations.push(Box::new(Union::smooth(a.clone(), b.clone(), blend)));
+                combinations.push(Box::new(Intersection::smooth(a.clone(), b.clone(), blend)));
+                combinations.push(Box::new(Subtraction::smooth(a.clone(), b.clone(), blend)));
+                combinations.push(Box::new(Xor::smooth(a, b, blend)));
+            }
+        }
+        combinations
+    }
+
+    #[test]
+    fn normals_match_the_distance_gradient_at_seams() {
+        let rng = fastrand::Rng::with_seed(0);
+        let gradient = |shape: &dyn Sdf, p: Vec3A, h: f32| {
+            Vec3A::new(
+                shape.distance(p + Vec3A::X * h) - shape.distance(p - Vec3A::X * h),
+                shape.distance(p + Vec3A::Y * h) - shape.distance(p - Vec3A::Y * h),
+                shape.distance(p + Vec3A::Z * h) - shape.distance(p - Vec3A::Z * h),
+            ) / (2.0 * h)
+        };
+        for shape in combinations() {
+            for _ in 0..500 {
+                let p = Vec3A::new(rng.f32(), rng.f32(), rng.f32()) * 3.0 - 1.5;
+                let fine = gradient(&*shape, p, 1e-3);
+                // Skip creases, where the difference depends on the step, and blends cancelling out
+                let coarse = gradient(&*shape, p, 4e-3);
+                if (fine - coarse).length() > 1e-2 || fine.length() < 0.2 {
+                    continue;
+                }
+                let gradient = fine.normalize();
+                let normal = shape.normal(p);
+                assert!(
+                    (normal - gradient).length() < 1e-2,
+                    "{normal} != {gradient} at {p}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn distances_are_bounded_and_inside_bounds() {
+        let rng = fastrand::Rng::with_seed(0);
+        let random = || Vec3A::new(rng.f32(), rng.f32(), rng.f32()) * 4.0 - 2.0;
+        for shape in combinations() {
+            let bounds = shape.bounds().unwrap();
+            for _ in 0..2000 {
+                let (p, q) = (random(), random());
+                let (dp, dq) = (shape.distance(p), shape.distance(q));
+                assert!((dp - dq).abs() <= p.distance(q) * 1.001);
+                if dp <= 0.0 {
+                    assert!(p.distance(bounds.center) <= bounds.radius + 1e-4);
+                }
+            }
+        }
+    }
+}
+
+/// Everything inside either surface.
+#[derive(Clone)]
+pub struct Union {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    blend: f32,
+}
+impl Union {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Self {
+        Self::smooth(a, b, 0.0)
+    }
+
+    /// Joins the surfaces with a fillet of about `blend` radius.
+    pub fn smooth(a: Box<dyn Sdf>, b: Box<dyn Sdf>, blend: f32) -> Self {
+        Union { a, b, blend }
+    }
+
+    #[inline(always)]
+    fn evaluate(&self, p: Vec3A) -> (f32, f32, f32) {
+        let (d, h) = smooth_min(self.a.distance(p), self.b.distance(p), self.blend);
+        (d, h, 1.0 - h)
+    }
+}
+impl Sdf for Union {
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        self.evaluate(ray_position).0
+    }
+
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let (_, wa, wb) = self.evaluate(ray_position);
+        blend_normals(&*self.a, &*self.b, ray_position, wa, wb)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Union {
+            a: Box::new(self.a.descriptor()?),
+            b: Box::new(self.b.descriptor()?),
+            blend: self.blend,
+        })
+    }
+
+    fn bounds(&self) -> Option<BoundingSphere> {
+        // The smooth minimum is at most a quarter of the blend radius below the minimum
+        Some(enclose(
+            self.a.bounds()?,
+            self.b.bounds()?,
+            self.blend * 0.25,
+        ))
+    }
+}
+
+/// Everything inside both surfaces.
+#[derive(Clone)]
+pub struct Intersection {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    blend: f32,
+}
+impl Intersection {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Self {
+        Self::smooth(a, b, 0.0)
+    }
+
+    /// Rounds the edges where the surfaces meet over about `blend` radius.
+    pub fn smooth(a: Box<dyn Sdf>, b: Box<dyn Sdf>, blend: f32) -> Self {
+        Intersection { a, b, blend }
+    }
+
+    #[inline(always)]
+    fn evaluate(&self, p: Vec3A) -> (f32, f32, f32) {
+        let (d, h) = smooth_max(self.a.distance(p), self.b.distance(p), self.blend);
+        (d, h, 1.0 - h)
+    }
+}
+impl Sdf for Intersection {
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        self.evaluate(ray_position).0
+    }
+
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let (_, wa, wb) = self.evaluate(ray_position);
+        blend_normals(&*self.a, &*self.b, ray_position, wa, wb)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Intersection {
+            a: Box::new(self.a.descriptor()?),
+            b: Box::new(self.b.descriptor()?),
+            blend: self.blend,
+        })
+    }
+
+    fn bounds(&self) -> Option<BoundingSphere> {
+        match (self.a.bounds(), self.b.bounds()) {
+            (Some(a), Some(b)) => Some(if a.radius < b.radius { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Everything inside `a` but outside `b`.
+#[derive(Clone)]
+pub struct Subtraction {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    blend: f32,
+}
+impl Subtraction {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Self {
+        Self::smooth(a, b, 0.0)
+    }
+
+    /// Rounds the edges of the carved out hole over about `blend` radius.
+    pub fn smooth(a: Box<dyn Sdf>, b: Box<dyn Sdf>, blend: f32) -> Self {
+        Subtraction { a, b, blend }
+    }
+
+    #[inline(always)]
+    fn evaluate(&self, p: Vec3A) -> (f32, f32, f32) {
+        let (d, h) = smooth_max(self.a.distance(p), -self.b.distance(p), self.blend);
+        (d, h, h - 1.0)
+    }
+}
+impl Sdf for Subtraction {
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        self.evaluate(ray_position).0
+    }
+
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let (_, wa, wb) = self.evaluate(ray_position);
+        blend_normals(&*self.a, &*self.b, ray_position, wa, wb)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Subtraction {
+            a: Box::new(self.a.descriptor()?),
+            b: Box::new(self.b.descriptor()?),
+            blend: self.blend,
+        })
+    }
+
+    fn bounds(&self) -> Option<BoundingSphere> {
+        self.a.bounds()
+    }
+}
+
+/// Everything inside exactly one of the surfaces.
+#[derive(Clone)]
+pub struct Xor {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    blend: f32,
+}
+impl Xor {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Self {
+        Self::smooth(a, b, 0.0)
+    }
+
+    /// Rounds every seam between the surfaces over about `blend` radius.
+    pub fn smooth(a: Box<dyn Sdf>, b: Box<dyn Sdf>, blend: f32) -> Self {
+        Xor { a, b, blend }
+    }
+
+    #[inline(always)]
+    fn evaluate(&self, p: Vec3A) -> (f32, f32, f32) {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        // The union, minus the intersection
+        let (union, u) = smooth_min(a, b, self.blend);
+        let (intersection, i) = smooth_max(a, b, self.blend);
+        let (d, h) = smooth_max(union, -intersection, self.blend);
+        (
+            d,
+            h * u - (1.0 - h) * i,
+            h * (1.0 - u) - (1.0 - h) * (1.0 - i),
+        )
+    }
+}
+impl Sdf for Xor {
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        self.evaluate(ray_position).0
+    }
+
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let (_, wa, wb) = self.evaluate(ray_position);
+        blend_normals(&*self.a, &*self.b, ray_position, wa, wb)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Xor {
+            a: Box::new(self.a.descriptor()?),
+            b: Box::new(self.b.descriptor()?),
+            blend: self.blend,
+        })
+    }
+
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(enclose(
+            self.a.bounds()?,
+            self.b.bounds()?,
+            self.blend * 0.25,
+        ))
+    }
+}
+
+/// The polynomial smooth minimum of `a` and `b`, and its derivative with respect to `a`. The
+/// derivative with respect to `b` is one minus that, so it also weighs the gradients of both.
+#[inline(always)]
+fn smooth_min(a: f32, b: f32, blend: f32) -> (f32, f32) {
+    if blend <= 0.0 {
+        return if a < b { (a, 1.0) } else { (b, 0.0) };
+    }
+    let h = (0.5 + 0.5 * (b - a) / blend).clamp(0.0, 1.0);
+    (b + (a - b) * h - blend * h * (1.0 - h), h)
+}
+
+#[inline(always)]
+fn smooth_max(a: f32, b: f32, blend: f32) -> (f32, f32) {
+    let (d, h) = smooth_min(-a, -b, blend);
+    (-d, h)
+}
+
+/// The normal of a combined surface, from the weights of the gradients of its parts. Parts with no
+/// weight don't affect the normal, so they aren't evaluated.
+#[inline(always)]
+fn blend_normals(a: &dyn Sdf, b: &dyn Sdf, p: Vec3A, wa: f32, wb: f32) -> Vec3A {
+    let mut gradient = Vec3A::ZERO;
+    if wa != 0.0 {
+        gradient += a.normal(p) * wa;
+    }
+    if wb != 0.0 {
+        gradient += b.normal(p) * wb;
+    }
+    gradient.normalize_or_zero()
+}
+
+/// The smallest sphere enclosing both spheres, grown by `margin`.
+fn enclose(a: BoundingSphere, b: BoundingSphere, margin: f32) -> BoundingSphere {
+    let (small, large) = if a.radius < b.radius { (a, b) } else { (b, a) };
+    let offset = small.center - large.center;
+    let distance = offset.length();
+    let enclosing = if distance + small.radius <= large.radius {
+        large
+    } else {
+        let radius = (distance + small.radius + large.radius) * 0.5;
+        BoundingSphere {
+            center: large.center + offset * ((radius - large.radius) / distance),
+            radius,
+        }
+    };
+    BoundingSphere {
+        radius: enclosing.radius + margin,
+        ..enclosing
+    }
+}
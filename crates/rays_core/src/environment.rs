@@ -0,0 +1,574 @@
+//! Light arriving from infinitely far away, seen wherever rays escape the scene.
+
+use std::{f32::consts::PI, path::PathBuf, sync::Arc};
+
+use glam::{Mat3, Quat, Vec2, Vec3, Vec3A};
+
+use crate::{
+    color::luminance,
+    image::{self, HdrImage, ImageError},
+    material::tangent_frame,
+    sampler::Sampler,
+    scene::EnvironmentDescriptor,
+};
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use glam::{Vec3, Vec3A};
+
+    use crate::{
+        environment::EnvironmentMap,
+        image::HdrImage,
+        sampler::{Independent, Sampler},
+    };
+
+    #[test]
+    fn map_sampling_matches_pdf() {
+        // A dark map of varying brightness, with one bright pixel
+        let (width, height) = (16, 8);
+        let rng = fastrand::Rng::with_seed(0);
+        let mut pixels: Vec<Vec3> = (0..width * height)
+            .map(|_| Vec3::splat(0.05 + 0.1 * rng.f32()))
+            .collect();
+        pixels[2 * width + 5] = Vec3::splat(100.0);
+        // Broken pixels, as PFM files can hold, are never sampled
+        pixels[4 * width + 1] = Vec3::splat(-1.0);
+        pixels[4 * width + 2] = Vec3::splat(f32::NAN);
+        let map = EnvironmentMap::from_image(
+            HdrImage {
+                size: [width as u32, height as u32],
+                pixels,
+            },
+            "test.hdr".into(),
+        )
+        .unwrap();
+
+        let mut sampler = Independent::default();
+        let samples = 200_000;
+        let mut histogram = vec![0u32; width * height];
+        for _ in 0..samples {
+            let (direction, pdf) = map.sample_direction(sampler.next_2d());
+            assert!((direction.length() - 1.0).abs() < 1e-4);
+            let expected = map.direction_pdf(direction);
+            assert!(
+                pdf > 0.0 && (pdf - expected).abs() <= 1e-3 * expected,
+                "{pdf} {expected}"
+            );
+            let (u, v) = EnvironmentMap::uv(direction);
+            let (x, y) = map.pixel(u, v);
+            histogram[x + y * width] += 1;
+        }
+        // Each pixel is hit as often as the density integrated over it predicts, which for a
+        // density constant over the image is its value at the center over the number of pixels
+        for (i, &count) in histogram.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let theta = (y as f32 + 0.5) / height as f32 * PI;
+            let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+            let center = Vec3A::new(
+                theta.sin() * phi.sin(),
+                theta.cos(),
+                -theta.sin() * phi.cos(),
+            );
+            let probability =
+                map.direction_pdf(center) * 2.0 * PI * PI * theta.sin() / (width * height) as f32;
+            let expected = probability * samples as f32;
+            let tolerance = 5.0 * expected.sqrt() + 2.0;
+            assert!(
+                (count as f32 - expected).abs() < tolerance,
+                "pixel {x},{y}: {count} samples, expected {expected}"
+            );
+        }
+        assert_eq!(histogram[4 * width + 1] + histogram[4 * width + 2], 0);
+        // The bright pixel holds most of the energy of the map
+        assert!(histogram[2 * width + 5] > samples * 8 / 10);
+
+        // Images without pixels can't be sampled
+        let empty = HdrImage {
+            size: [0, 8],
+            pixels: Vec::new(),
+        };
+        assert!(EnvironmentMap::from_image(empty, "empty.hdr".into()).is_err());
+    }
+}
+
+/// The radiance arriving from every direction around the scene.
+pub trait Environment: Send + Sync {
+    /// Radiance arriving from the normalized `direction`.
+    fn radiance(&self, direction: Vec3A) -> Vec3;
+    /// Samples a direction in proportion to the light arriving from it, returning the direction
+    /// and its pdf per unit solid angle. Environments that aren't worth sampling explicitly, like
+    /// a dim uniform sky, return `None`.
+    fn sample(&self, _sampler: &mut dyn Sampler) -> Option<(Vec3A, f32)> {
+        None
+    }
+    /// Probability density of [`Environment::sample`] returning `direction`.
+    fn pdf(&self, _direction: Vec3A) -> f32 {
+        0.0
+    }
+    /// `true` if [`Environment::sample`] can return directions.
+    fn is_sampled(&self) -> bool {
+        false
+    }
+    /// Describes this environment for the scene file format. Environments that can't be written
+    /// to a scene file return `None`.
+    fn descriptor(&self) -> Option<EnvironmentDescriptor> {
+        None
+    }
+}
+
+/// An [`Environment`] placed around the scene, rotated and scaled in brightness.
+#[derive(Clone)]
+pub struct Background {
+    pub environment: Arc<dyn Environment>,
+    /// Rotation of the environment around the vertical axis, in radians.
+    pub rotation: f32,
+    /// Multiplier applied to the radiance of the environment.
+    pub intensity: f32,
+}
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            environment: Arc::new(Sky::default()),
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+}
+impl Background {
+    #[inline(always)]
+    pub fn radiance(&self, direction: Vec3A) -> Vec3 {
+        let local = Quat::from_rotation_y(-self.rotation) * direction;
+        self.environment.radiance(local) * self.intensity
+    }
+
+    /// See [`Environment::sample`].
+    #[inline(always)]
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vec3A, f32)> {
+        let (local, pdf) = self.environment.sample(sampler)?;
+        Some((Quat::from_rotation_y(self.rotation) * local, pdf))
+    }
+
+    /// See [`Environment::pdf`].
+    #[inline(always)]
+    pub fn pdf(&self, direction: Vec3A) -> f32 {
+        self.environment
+            .pdf(Quat::from_rotation_y(-self.rotation) * direction)
+    }
+
+    /// `true` if the environment is sampled as a light source.
+    #[inline(always)]
+    pub fn is_sampled(&self) -> bool {
+        self.intensity > 0.0 && self.environment.is_sampled()
+    }
+}
+
+/// A clear daytime sky lit by the sun, using the analytic model from "A Practical Analytic Model
+/// for Daylight" by Preetham, Shirley and Smits.
+///
+/// Radiance is in units of 10 kcd/m², which puts a clear sky around 1.0. The sun is a disk of its
+/// real angular size, dimmed and reddened by the atmosphere, and is sampled as a light source.
+#[derive(Clone, Debug)]
+pub struct Sky {
+    /// Sun elevation above the horizon, in radians.
+    elevation: f32,
+    /// Sun azimuth in radians, clockwise from -Z when seen from above.
+    azimuth: f32,
+    /// Haziness of the atmosphere, from 2.0 for a very clear sky to 10.0 for a hazy one.
+    turbidity: f32,
+    sun_direction: Vec3A,
+    sun_radiance: Vec3,
+    /// Perez distribution coefficients of the Y, x and y channels.
+    perez: [[f32; 5]; 3],
+    /// Y, x and y at the zenith.
+    zenith: Vec3,
+}
+impl Default for Sky {
+    fn default() -> Self {
+        Sky::new(45f32.to_radians(), 0.0, 3.0)
+    }
+}
+impl Sky {
+    /// Luminance of 1.0 in cd/m².
+    const UNIT: f32 = 10_000.0;
+    /// Angular radius of the sun, in radians.
+    const SUN_RADIUS: f32 = 0.00465;
+    /// Luminance of the sun outside the atmosphere, in cd/m².
+    const SUN_LUMINANCE: f32 = 2.0e9;
+
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        let elevation = elevation.clamp(0.0, PI / 2.0);
+        let turbidity = turbidity.clamp(1.7, 10.0);
+        let t = turbidity;
+        let sun_direction = Vec3A::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // Zenith luminance in kcd/m², and chromaticity
+        let theta_s = PI / 2.0 - elevation;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta = Vec3::new(theta_s.powi(3), theta_s.powi(2), theta_s);
+        let x = t * t * Vec3::new(0.00166, -0.00375, 0.00209).dot(theta)
+            + t * Vec3::new(-0.02903, 0.06377, -0.03202).dot(theta)
+            + 0.00394
+            + Vec3::new(0.11693, -0.21196, 0.06052).dot(theta)
+            + 0.25886;
+        let y = t * t * Vec3::new(0.00275, -0.00610, 0.00317).dot(theta)
+            + t * Vec3::new(-0.04214, 0.08970, -0.04153).dot(theta)
+            + 0.00516
+            + Vec3::new(0.15346, -0.26756, 0.06670).dot(theta)
+            + 0.26688;
+        let zenith = Vec3::new(luminance * 1000.0 / Self::UNIT, x, y);
+
+        // Transmittance of the atmosphere along the path of sunlight, from Rayleigh scattering and
+        // aerosols, at wavelengths standing in for red, green and blue
+        let air_mass =
+            1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).max(0.1).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = [0.68f32, 0.55, 0.44].map(|wavelength| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+            (-(rayleigh + aerosol) * air_mass).exp()
+        });
+        let sun_radiance = Vec3::from(transmittance) * Self::SUN_LUMINANCE / Self::UNIT;
+
+        Self {
+            elevation,
+            azimuth,
+            turbidity,
+            sun_direction,
+            sun_radiance,
+            perez,
+            zenith,
+        }
+    }
+
+    /// The Perez sky distribution, for a view at `theta` from the zenith and `gamma` from the sun.
+    #[inline(always)]
+    fn perez(coefficients: [f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = coefficients;
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+
+    #[inline(always)]
+    fn sky_radiance(&self, direction: Vec3A) -> Vec3 {
+        // The ground below the horizon is shown as the haze at the horizon
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_s = PI / 2.0 - self.elevation;
+
+        let mut yxy = [0.0; 3];
+        for (i, value) in yxy.iter_mut().enumerate() {
+            *value = self.zenith[i] * Self::perez(self.perez[i], cos_theta, gamma)
+                / Self::perez(self.perez[i], 1.0, theta_s);
+        }
+        let [luminance, x, y] = yxy;
+
+        // xyY to XYZ, then to linear sRGB
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let to_srgb = Mat3::from_cols(
+            Vec3::new(3.2406, -1.5372, -0.4986),
+            Vec3::new(-0.9689, 1.8758, 0.0415),
+            Vec3::new(0.0557, -0.2040, 1.0570),
+        )
+        .transpose();
+        (to_srgb * xyz).max(Vec3::ZERO)
+    }
+
+    /// `1 - cos` of the angular radius of the sun.
+    #[inline(always)]
+    fn sun_one_minus_cos() -> f32 {
+        let half = Self::SUN_RADIUS / 2.0;
+        2.0 * half.sin() * half.sin()
+    }
+}
+impl Environment for Sky {
+    #[inline(always)]
+    fn radiance(&self, direction: Vec3A) -> Vec3 {
+        let sky = self.sky_radiance(direction);
+        if direction.dot(self.sun_direction) >= 1.0 - Self::sun_one_minus_cos() {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    /// Samples the sun disk. The rest of the sky is dim enough to be found by scattering.
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vec3A, f32)> {
+        let one_minus_cos = Self::sun_one_minus_cos();
+        let u = sampler.next_2d();
+        let cos_theta = 1.0 - u.x * one_minus_cos;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y;
+        let (tangent, bitangent) = tangent_frame(self.sun_direction);
+        let direction = (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta
+            + self.sun_direction * cos_theta;
+        Some((direction, 1.0 / (2.0 * PI * one_minus_cos)))
+    }
+
+    fn pdf(&self, direction: Vec3A) -> f32 {
+        let one_minus_cos = Self::sun_one_minus_cos();
+        if direction.dot(self.sun_direction) >= 1.0 - one_minus_cos {
+            1.0 / (2.0 * PI * one_minus_cos)
+        } else {
+            0.0
+        }
+    }
+
+    fn is_sampled(&self) -> bool {
+        true
+    }
+
+    fn descriptor(&self) -> Option<EnvironmentDescriptor> {
+        Some(EnvironmentDescriptor::Sky {
+            elevation: self.elevation.to_degrees(),
+            azimuth: self.azimuth.to_degrees(),
+            turbidity: self.turbidity,
+        })
+    }
+}
+
+/// A simple sky fading from white at the horizon to blue overhead.
+#[derive(Clone, Debug)]
+pub struct Gradient;
+impl Environment for Gradient {
+    #[inline(always)]
+    fn radiance(&self, direction: Vec3A) -> Vec3 {
+        let t = 0.5 * (direction.y + 1.0);
+        (1.0 - t) + t * Vec3::new(0.5, 0.7, 1.0)
+    }
+
+    fn descriptor(&self) -> Option<EnvironmentDescriptor> {
+        Some(EnvironmentDescriptor::Gradient)
+    }
+}
+
+/// An equirectangular HDR image, importance sampled by the brightness of its pixels.
+///
+/// The center of the image is towards -Z, and its top row is straight up.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    path: PathBuf,
+    size: [u32; 2],
+    /// Pixels row by row, from the top.
+    pixels: Vec<Vec3>,
+    /// Distribution of the rows, then of the pixels in each row.
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+impl EnvironmentMap {
+    /// Loads a Radiance `.hdr` or `.pfm` image.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ImageError> {
+        let path = path.into();
+        Self::from_image(image::load_hdr(&path)?, path)
+    }
+
+    /// Builds a map from an already loaded image. `path` is only used to describe the map in a
+    /// scene file. Fails if the image is empty, or its size doesn't match its pixels.
+    pub fn from_image(image: HdrImage, path: PathBuf) -> Result<Self, ImageError> {
+        let [width, height] = image.size;
+        if width == 0 || height == 0 || image.pixels.len() as u64 != width as u64 * height as u64 {
+            return Err(ImageError::Decode(format!(
+                "environment map size {width}x{height} doesn't match its {} pixels",
+                image.pixels.len()
+            )));
+        }
+        let columns: Vec<Distribution> = image
+            .pixels
+            .chunks_exact(width as usize)
+            .enumerate()
+            .map(|(row, pixels)| {
+                // Rows near the poles cover less of the sphere
+                let sin_theta = ((row as f32 + 0.5) / height as f32 * PI).sin();
+                Distribution::new(
+                    pixels
+                        .iter()
+                        .map(|pixel| luminance(*pixel) * sin_theta)
+                        .collect(),
+                )
+            })
+            .collect();
+        let rows = Distribution::new(columns.iter().map(|row| row.integral).collect());
+        Ok(Self {
+            path,
+            size: image.size,
+            pixels: image.pixels,
+            rows,
+            columns,
+        })
+    }
+
+    /// Coordinates of `direction` in the image, in `[0, 1]` from the top left.
+    #[inline(always)]
+    fn uv(direction: Vec3A) -> (f32, f32) {
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    #[inline(always)]
+    fn pixel(&self, u: f32, v: f32) -> (usize, usize) {
+        let [width, height] = self.size;
+        let x = ((u * width as f32) as usize).min(width as usize - 1);
+        let y = ((v * height as f32) as usize).min(height as usize - 1);
+        (x, y)
+    }
+
+    #[inline(always)]
+    fn lookup(&self, direction: Vec3A) -> Vec3 {
+        let (u, v) = Self::uv(direction);
+        let (x, y) = self.pixel(u, v);
+        self.pixels[x + y * self.size[0] as usize]
+    }
+
+    #[inline(always)]
+    fn sample_direction(&self, u: Vec2) -> (Vec3A, f32) {
+        let (v, y) = self.rows.sample(u.y);
+        let (u, _) = self.columns[y].sample(u.x);
+        let (theta, phi) = (v * PI, (u - 0.5) * 2.0 * PI);
+        let direction = Vec3A::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        // Looked up from the direction rather than the sampled pieces, so directions rounded
+        // into a neighboring pixel get the same density as when they are hit by scattering
+        (direction, self.direction_pdf(direction))
+    }
+
+    #[inline(always)]
+    fn direction_pdf(&self, direction: Vec3A) -> f32 {
+        let (u, v) = Self::uv(direction);
+        let (x, y) = self.pixel(u, v);
+        let row = &self.columns[y];
+        if self.rows.integral <= 0.0 || row.integral <= 0.0 {
+            return 0.0;
+        }
+        let pdf = self.rows.values[y] / self.rows.integral * row.values[x] / row.integral;
+        Self::solid_angle_pdf(pdf, v * PI)
+    }
+
+    /// Converts a density over the image to one over directions.
+    #[inline(always)]
+    fn solid_angle_pdf(image_pdf: f32, theta: f32) -> f32 {
+        let sin_theta = theta.sin();
+        if sin_theta <= 0.0 {
+            0.0
+        } else {
+            image_pdf / (2.0 * PI * PI * sin_theta)
+        }
+    }
+}
+impl Environment for EnvironmentMap {
+    #[inline(always)]
+    fn radiance(&self, direction: Vec3A) -> Vec3 {
+        self.lookup(direction)
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vec3A, f32)> {
+        if self.rows.integral <= 0.0 {
+            return None;
+        }
+        let (direction, pdf) = self.sample_direction(sampler.next_2d());
+        (pdf > 0.0).then_some((direction, pdf))
+    }
+
+    fn pdf(&self, direction: Vec3A) -> f32 {
+        self.direction_pdf(direction)
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.rows.integral > 0.0
+    }
+
+    fn descriptor(&self) -> Option<EnvironmentDescriptor> {
+        Some(EnvironmentDescriptor::Map {
+            path: self.path.clone(),
+        })
+    }
+}
+
+/// A piecewise constant distribution over `[0, 1]`, with one piece per value.
+#[derive(Clone, Debug)]
+struct Distribution {
+    /// Values of the pieces, with negative and non-finite values replaced by zero.
+    values: Vec<f32>,
+    /// Cumulative distribution at the end of each piece.
+    cdf: Vec<f32>,
+    /// Integral of the function over `[0, 1]`.
+    integral: f32,
+}
+impl Distribution {
+    fn new(mut values: Vec<f32>) -> Self {
+        // Cleaned up once, so densities read from the values agree with the cdf
+        for value in &mut values {
+            if !value.is_finite() || *value < 0.0 {
+                *value = 0.0;
+            }
+        }
+        let n = values.len() as f32;
+        let mut cdf = Vec::with_capacity(values.len());
+        let mut sum = 0.0;
+        for value in &values {
+            sum += value / n;
+            cdf.push(sum);
+        }
+        if sum > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= sum);
+        }
+        Self {
+            values,
+            cdf,
+            integral: sum,
+        }
+    }
+
+    /// Maps a uniform random number in `[0, 1)` to a point of the distribution, returning the
+    /// point and the index of its piece.
+    #[inline(always)]
+    fn sample(&self, random: f32) -> (f32, usize) {
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= random)
+            .min(self.values.len() - 1);
+        let start = if index == 0 { 0.0 } else { self.cdf[index - 1] };
+        let width = self.cdf[index] - start;
+        let offset = if width > 0.0 {
+            (random - start) / width
+        } else {
+            0.5
+        };
+        let x = (index as f32 + offset) / self.values.len() as f32;
+        (x, index)
+    }
+}
@@ -0,0 +1,153 @@
+use glam::Vec4;
+
+use crate::{
+    color::Color,
+    tile::{Tile, TileRegion},
+    tonemap::DisplayTransform,
+};
+
+/// Accumulates the linear radiance sent by [`crate::PathTracer`], along with the number of samples
+/// taken for every pixel.
+///
+/// Pixels are stored row by row, with the first row at the bottom of the image.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    size: [u32; 2],
+    /// Sum of all samples taken for each pixel.
+    radiance: Vec<Color>,
+    samples: Vec<u32>,
+}
+impl Framebuffer {
+    pub fn new(size: [u32; 2]) -> Self {
+        let area = (size[0] * size[1]) as usize;
+        Self {
+            size,
+            radiance: vec![Color::from(Vec4::ZERO); area],
+            samples: vec![0; area],
+        }
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    #[inline(always)]
+    fn index(&self, position: [u32; 2]) -> usize {
+        (position[0] + position[1] * self.size[0]) as usize
+    }
+
+    /// Adds the samples carried by the pixels of `tile` to the accumulated radiance.
+    pub fn add(&mut self, tile: &Tile) {
+        let TileRegion { origin, size } = tile.region;
+        for (row, pixels) in tile.pixels.chunks_exact(size[0] as usize).enumerate() {
+            let start = self.index([origin[0], origin[1] + row as u32]);
+            for (i, pixel) in pixels.iter().enumerate() {
+                self.radiance[start + i] += pixel.radiance;
+                self.samples[start + i] += pixel.samples;
+            }
+        }
+    }
+
+    /// Number of samples accumulated at `position`.
+    #[inline(always)]
+    pub fn samples(&self, position: [u32; 2]) -> u32 {
+        self.samples[self.index(position)]
+    }
+
+    /// Mean linear radiance at `position`, or black if no samples were taken yet.
+    #[inline(always)]
+    pub fn linear(&self, position: [u32; 2]) -> Color {
+        let index = self.index(position);
+        mean(&self.radiance[index], self.samples[index])
+    }
+
+    /// Mean linear radiance of every pixel.
+    pub fn linear_pixels(&self) -> impl Iterator<Item = Color> + '_ {
+        self.radiance
+            .iter()
+            .zip(&self.samples)
+            .map(|(radiance, &samples)| mean(radiance, samples))
+    }
+
+    /// The image converted for display as RGBA8. Pixels without any samples are transparent.
+    pub fn to_rgba8(&self, display: &DisplayTransform) -> Vec<u8> {
+        self.region_rgba8(
+            TileRegion {
+                origin: [0, 0],
+                size: self.size,
+            },
+            display,
+        )
+    }
+
+    /// A region of the image converted for display as RGBA8, row by row from its bottom row.
+    pub fn region_rgba8(&self, region: TileRegion, display: &DisplayTransform) -> Vec<u8> {
+        let TileRegion { origin, size } = region;
+        let mut rgba = Vec::with_capacity((size[0] * size[1] * 4) as usize);
+        for y in origin[1]..origin[1] + size[1] {
+            let start = self.index([origin[0], y]);
+            let end = start + size[0] as usize;
+            for (radiance, &samples) in self.radiance[start..end]
+                .iter()
+                .zip(&self.samples[start..end])
+            {
+                if samples == 0 {
+                    rgba.extend_from_slice(&[0; 4]);
+                } else {
+                    rgba.extend_from_slice(&display.apply(mean(radiance, samples)));
+                }
+            }
+        }
+        rgba
+    }
+
+    /// The number of samples of each pixel as RGBA8, from dark purple for the fewest to yellow for
+    /// the most. Pixels without any samples are transparent.
+    pub fn heatmap_rgba8(&self) -> Vec<u8> {
+        let (min, max) = self
+            .samples
+            .iter()
+            .filter(|&&samples| samples > 0)
+            .fold((u32::MAX, 0), |(min, max), &samples| {
+                (min.min(samples), max.max(samples))
+            });
+        let range = max.saturating_sub(min).max(1) as f32;
+        self.samples
+            .iter()
+            .flat_map(|&samples| {
+                if samples == 0 {
+                    [0; 4]
+                } else {
+                    heat((samples - min) as f32 / range)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Maps `t` in `[0, 1]` to an sRGB color of the viridis color map.
+#[inline(always)]
+fn heat(t: f32) -> [u8; 4] {
+    const STOPS: [[f32; 3]; 5] = [
+        [68.0, 1.0, 84.0],
+        [59.0, 82.0, 139.0],
+        [33.0, 145.0, 140.0],
+        [94.0, 201.0, 98.0],
+        [253.0, 231.0, 37.0],
+    ];
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f32;
+    let [r, g, b] = [0, 1, 2].map(|c| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f) as u8);
+    [r, g, b, 255]
+}
+
+#[inline(always)]
+fn mean(radiance: &Color, samples: u32) -> Color {
+    if samples == 0 {
+        Color::from(Vec4::ZERO)
+    } else {
+        radiance / samples as f32
+    }
+}
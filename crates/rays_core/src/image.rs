@@ -0,0 +1,401 @@
+//! Writes finished renders to disk as PNG, PPM or PFM images, and reads HDR images used as
+//! environment maps.
+//!
+//! Buffers passed to the writers start with the bottom row of the image, in the same layout as
+//! [`Framebuffer`].
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    mem,
+    path::Path,
+};
+
+use glam::Vec3;
+
+use crate::{framebuffer::Framebuffer, tonemap::DisplayTransform};
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::image::{read_pfm, read_rgbe, write_pfm, write_ppm, ImageError};
+
+    #[test]
+    fn ppm_rows_are_flipped() {
+        // Bottom row red, top row blue
+        let rgba = [255, 0, 0, 255, 0, 0, 255, 255];
+        let mut out = Vec::new();
+        write_ppm(&mut out, [1, 2], &rgba).unwrap();
+        assert_eq!(out, b"P6\n1 2\n255\n\x00\x00\xff\xff\x00\x00");
+    }
+
+    #[test]
+    fn pfm_header() {
+        let mut out = Vec::new();
+        write_pfm(&mut out, [2, 1], &[0.0; 6]).unwrap();
+        assert!(out.starts_with(b"PF\n2 1\n-1.0\n"));
+        assert_eq!(out.len(), 12 + 6 * 4);
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        // Bottom row first
+        let rgb = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let mut out = Vec::new();
+        write_pfm(&mut out, [1, 2], &rgb).unwrap();
+        let image = read_pfm(out.as_slice()).unwrap();
+        assert_eq!(image.size, [1, 2]);
+        assert_eq!(
+            image.pixels,
+            [Vec3::new(4.0, 5.0, 6.0), Vec3::new(1.0, 2.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn rgbe_run_length_encoded() {
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        file.extend_from_slice(&[2, 2, 0, 8]);
+        // Each channel is a run of 8 identical bytes, and 1.0 is stored as (128, 129)
+        for byte in [128, 128, 64, 129] {
+            file.extend_from_slice(&[128 + 8, byte]);
+        }
+        let image = read_rgbe(file.as_slice()).unwrap();
+        assert_eq!(image.size, [8, 1]);
+        assert!(image.pixels.iter().all(|&p| p == Vec3::new(1.0, 1.0, 0.5)));
+    }
+
+    #[test]
+    fn bad_resolutions_are_errors() {
+        for resolution in ["0 +X 8", "4 +X 0", "4294967295 +X 4294967295"] {
+            let file = format!("#?RADIANCE\n\n-Y {resolution}\n\x02\x02\x00\x08");
+            let error = read_rgbe(file.as_bytes()).unwrap_err();
+            assert!(matches!(error, ImageError::Decode(_)), "{error}");
+        }
+        for size in ["0 1", "1 0", "4294967295 4294967295"] {
+            let file = format!("PF\n{size}\n-1.0\n\0\0\0\0");
+            let error = read_pfm(file.as_bytes()).unwrap_err();
+            assert!(matches!(error, ImageError::Decode(_)), "{error}");
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// 8-bit RGB PNG.
+    Png,
+    /// 8-bit binary (P6) PPM.
+    Ppm,
+    /// 32-bit float RGB PFM.
+    Pfm,
+}
+impl ImageFormat {
+    /// Picks the format from the extension of `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    /// The file extension doesn't match any [`ImageFormat`].
+    UnknownFormat,
+    /// An image being read is malformed, or uses an unsupported variant of its format.
+    Decode(String),
+}
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{e}"),
+            ImageError::Png(e) => write!(f, "{e}"),
+            ImageError::UnknownFormat => {
+                write!(f, "unknown image format, expected png, ppm or pfm")
+            }
+            ImageError::Decode(message) => write!(f, "{message}"),
+        }
+    }
+}
+impl std::error::Error for ImageError {}
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+impl From<png::EncodingError> for ImageError {
+    fn from(e: png::EncodingError) -> Self {
+        ImageError::Png(e)
+    }
+}
+
+/// Saves a render, picking the format from the extension of `path`. PNG and PPM store the image
+/// after the `display` transform, while PFM stores the linear radiance.
+pub fn save(
+    path: impl AsRef<Path>,
+    framebuffer: &Framebuffer,
+    display: &DisplayTransform,
+) -> Result<(), ImageError> {
+    let format = ImageFormat::from_path(&path).ok_or(ImageError::UnknownFormat)?;
+    let size = framebuffer.size();
+    let mut file = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png => write_png(&mut file, size, &framebuffer.to_rgba8(display))?,
+        ImageFormat::Ppm => write_ppm(&mut file, size, &framebuffer.to_rgba8(display))?,
+        ImageFormat::Pfm => {
+            let rgb: Vec<f32> = framebuffer
+                .linear_pixels()
+                .flat_map(|color| color.rgb().to_array())
+                .collect();
+            write_pfm(&mut file, size, &rgb)?
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes an sRGB encoded RGBA8 buffer as an 8-bit RGB PNG.
+pub fn write_png<W: Write>(writer: W, size: [u32; 2], rgba: &[u8]) -> Result<(), ImageError> {
+    let mut encoder = png::Encoder::new(writer, size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&top_down_rgb(size, rgba))?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes an RGBA8 buffer as a binary (P6) PPM.
+pub fn write_ppm<W: Write>(mut writer: W, size: [u32; 2], rgba: &[u8]) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", size[0], size[1])?;
+    writer.write_all(&top_down_rgb(size, rgba))
+}
+
+/// Writes linear RGB floats as a little-endian PFM. PFM stores rows bottom to top, like
+/// [`Framebuffer`], so no flip is needed.
+pub fn write_pfm<W: Write>(mut writer: W, size: [u32; 2], rgb: &[f32]) -> io::Result<()> {
+    write!(writer, "PF\n{} {}\n-1.0\n", size[0], size[1])?;
+    for channel in rgb {
+        writer.write_all(&channel.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Drops the alpha channel and reorders rows from bottom-up to top-down.
+fn top_down_rgb(size: [u32; 2], rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(size[0] as usize * 4)
+        .rev()
+        .flat_map(|row| row.chunks_exact(4).flat_map(|pixel| &pixel[..3]))
+        .copied()
+        .collect()
+}
+
+/// A linear RGB image, stored row by row from the top.
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    pub size: [u32; 2],
+    pub pixels: Vec<Vec3>,
+}
+
+/// Loads a Radiance `.hdr` or `.pfm` image, picking the format from the extension of `path`.
+pub fn load_hdr(path: impl AsRef<Path>) -> Result<HdrImage, ImageError> {
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let reader = || File::open(&path).map(BufReader::new);
+    match extension.as_deref() {
+        Some("hdr") => read_rgbe(reader()?),
+        Some("pfm") => read_pfm(reader()?),
+        _ => Err(ImageError::Decode(
+            "unknown HDR image format, expected hdr or pfm".into(),
+        )),
+    }
+}
+
+/// Reads a Radiance RGBE (`.hdr`) image, with or without run length encoding.
+pub fn read_rgbe<R: BufRead>(mut reader: R) -> Result<HdrImage, ImageError> {
+    let decode_error = |message: &str| ImageError::Decode(format!("invalid .hdr file: {message}"));
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(decode_error("missing #? signature"));
+    }
+    // Header variables, up to an empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(decode_error("unexpected end of header"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(decode_error(&format!("unsupported format {format}")));
+            }
+        }
+    }
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<u32>(), width.parse::<u32>()),
+        _ => return Err(decode_error("only -Y +X orientations are supported")),
+    };
+    let (Ok(height), Ok(width)) = (height, width) else {
+        return Err(decode_error("invalid resolution"));
+    };
+    byte_size(width, height, mem::size_of::<Vec3>())
+        .ok_or_else(|| decode_error("invalid resolution"))?;
+
+    // Memory is only allocated as pixels are read, so a bogus resolution can't exhaust it
+    let mut pixels = Vec::new();
+    let encodable = (8..0x8000).contains(&width);
+    let mut scanline = vec![[0u8; 4]; if encodable { width as usize } else { 0 }];
+    for _ in 0..height {
+        let mut start = [0u8; 4];
+        reader.read_exact(&mut start)?;
+        let run_length_encoded =
+            encodable && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0;
+        if run_length_encoded {
+            if u32::from(start[2]) << 8 | u32::from(start[3]) != width {
+                return Err(decode_error("scanline width mismatch"));
+            }
+            // Each channel is stored separately, as runs or literal spans of bytes
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < scanline.len() {
+                    let mut count = [0u8; 1];
+                    reader.read_exact(&mut count)?;
+                    let (count, run) = match count[0] {
+                        count if count > 128 => ((count - 128) as usize, true),
+                        count => (count as usize, false),
+                    };
+                    if count == 0 || x + count > scanline.len() {
+                        return Err(decode_error("bad run length"));
+                    }
+                    if run {
+                        let mut value = [0u8; 1];
+                        reader.read_exact(&mut value)?;
+                        for pixel in &mut scanline[x..x + count] {
+                            pixel[channel] = value[0];
+                        }
+                    } else {
+                        for pixel in &mut scanline[x..x + count] {
+                            let mut value = [0u8; 1];
+                            reader.read_exact(&mut value)?;
+                            pixel[channel] = value[0];
+                        }
+                    }
+                    x += count;
+                }
+            }
+            pixels.extend(scanline.iter().map(|&pixel| from_rgbe(pixel)));
+        } else {
+            pixels.push(from_rgbe(start));
+            for _ in 1..width {
+                let mut pixel = [0u8; 4];
+                reader.read_exact(&mut pixel)?;
+                pixels.push(from_rgbe(pixel));
+            }
+        }
+    }
+
+    Ok(HdrImage {
+        size: [width, height],
+        pixels,
+    })
+}
+
+#[inline(always)]
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        Vec3::ZERO
+    } else {
+        Vec3::new(r as f32, g as f32, b as f32) * 2f32.powi(e as i32 - 136)
+    }
+}
+
+/// The size of an image in bytes, or `None` if it's empty or too large to address.
+fn byte_size(width: u32, height: u32, pixel_size: usize) -> Option<usize> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    (width as usize)
+        .checked_mul(height as usize)?
+        .checked_mul(pixel_size)
+}
+
+/// Reads a color (`PF`) or greyscale (`Pf`) PFM image, in either byte order.
+pub fn read_pfm<R: BufRead>(mut reader: R) -> Result<HdrImage, ImageError> {
+    let decode_error = |message: &str| ImageError::Decode(format!("invalid .pfm file: {message}"));
+
+    let mut header = String::new();
+    // Magic number, size and scale, which may share lines
+    while header.split_whitespace().count() < 4 {
+        if reader.read_line(&mut header)? == 0 {
+            return Err(decode_error("unexpected end of header"));
+        }
+    }
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    let channels: usize = match fields[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(decode_error("missing PF signature")),
+    };
+    let (Ok(width), Ok(height), Ok(scale)) = (
+        fields[1].parse::<u32>(),
+        fields[2].parse::<u32>(),
+        fields[3].parse::<f32>(),
+    ) else {
+        return Err(decode_error("invalid header"));
+    };
+
+    let len = byte_size(width, height, channels * mem::size_of::<f32>())
+        .ok_or_else(|| decode_error("invalid resolution"))?;
+    // Read up to the expected length rather than allocating it upfront, in case it's bogus
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    let values: Vec<f32> = data
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if scale < 0.0 {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            }
+        })
+        .collect();
+
+    // Rows are stored from the bottom
+    let pixels = values
+        .chunks_exact(width as usize * channels)
+        .rev()
+        .flat_map(|row| row.chunks_exact(channels))
+        .map(|pixel| match *pixel {
+            [r, g, b] => Vec3::new(r, g, b),
+            [v] => Vec3::splat(v),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    Ok(HdrImage {
+        size: [width, height],
+        pixels,
+    })
+}
@@ -0,0 +1,67 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use crossbeam_channel::Receiver;
+
+use crate::tile::Tile;
+
+/// Why a render job stopped running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderOutcome {
+    /// Every pixel was rendered.
+    Completed,
+    /// The job was stopped early with [`RenderJob::cancel`].
+    Cancelled,
+    /// The render thread panicked.
+    Panicked,
+}
+
+/// A handle to a render running in the background, returned by [`crate::PathTracer::run`].
+///
+/// Tiles are streamed through [`RenderJob::receiver`] as they are finished.
+pub struct RenderJob {
+    receiver: Receiver<Tile>,
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<RenderOutcome>,
+}
+impl RenderJob {
+    pub(crate) fn new(
+        receiver: Receiver<Tile>,
+        cancelled: Arc<AtomicBool>,
+        handle: JoinHandle<RenderOutcome>,
+    ) -> Self {
+        Self {
+            receiver,
+            cancelled,
+            handle,
+        }
+    }
+
+    /// The channel finished tiles are sent through.
+    #[inline(always)]
+    pub fn receiver(&self) -> &Receiver<Tile> {
+        &self.receiver
+    }
+
+    /// Asks the render threads to stop. Tiles that are only partially traced are dropped.
+    #[inline(always)]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` once the render thread has stopped, whether it completed or not.
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Blocks until the render thread stops, and reports why it stopped.
+    pub fn join(self) -> RenderOutcome {
+        self.handle.join().unwrap_or(RenderOutcome::Panicked)
+    }
+}
@@ -1,25 +1,120 @@
 use color::Color;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use dyn_clone::{clone_trait_object, DynClone};
-use glam::{Mat4, Vec3, Vec3A, Vec4, Vec4Swizzles};
+use environment::Background;
+use glam::{Mat4, Vec3, Vec3A, Vec4};
+use job::{RenderJob, RenderOutcome};
+use light::Light;
 use material::Material;
 use ray::Ray;
 use rayon::prelude::*;
-use std::{f32::consts::PI, sync::Arc};
+use sampler::Sampler;
+use scene::ShapeDescriptor;
+use std::{
+    collections::BTreeMap,
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tile::{Tile, TileOrder};
 
 pub mod color;
+pub mod csg;
+pub mod environment;
+pub mod framebuffer;
+pub mod image;
+pub mod job;
+pub mod light;
 pub mod material;
 pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod sdf;
+pub mod tile;
+pub mod tonemap;
+pub mod transform;
+
+#[cfg(test)]
+mod tests {
+    use crate::{framebuffer::Framebuffer, tile::TileOrder, PathTracer, RenderSettings, Scene};
+
+    fn render(settings: RenderSettings) -> Framebuffer {
+        let size = [24, 16];
+        let job = PathTracer::build(size).run(Scene::demo(1.5), settings);
+        let mut framebuffer = Framebuffer::new(size);
+        for tile in job.receiver().iter() {
+            framebuffer.add(&tile);
+        }
+        job.join();
+        framebuffer
+    }
+
+    fn pixels(framebuffer: &Framebuffer) -> Vec<[f32; 4]> {
+        framebuffer
+            .linear_pixels()
+            .map(|color| color.inner.to_array())
+            .collect()
+    }
+
+    #[test]
+    fn renders_are_reproducible() {
+        let settings = RenderSettings {
+            samples: 4,
+            ..Default::default()
+        };
+        let image = pixels(&render(settings.clone()));
+        // Tiles are traced in a different order, by different threads
+        let reordered = render(RenderSettings {
+            tile_size: 5,
+            tile_order: TileOrder::Scanline,
+            ..settings.clone()
+        });
+        assert_eq!(image, pixels(&reordered));
+        assert_ne!(
+            image,
+            pixels(&render(RenderSettings {
+                seed: 1,
+                ..settings
+            }))
+        );
+    }
+
+    #[test]
+    fn adaptive_sampling_moves_samples_to_noisy_pixels() {
+        let samples = 32;
+        let framebuffer = render(RenderSettings {
+            samples,
+            adaptive_threshold: Some(0.02),
+            ..Default::default()
+        });
+        let [width, height] = framebuffer.size();
+        let counts: Vec<u32> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| [x, y]))
+            .map(|position| framebuffer.samples(position))
+            .collect();
+        let total: u32 = counts.iter().sum();
+        assert!(total <= samples as u32 * width * height);
+        assert!(counts.iter().any(|&count| count < samples as u32));
+        assert!(counts.iter().any(|&count| count > samples as u32));
+    }
+}
 
 pub struct PathTracer {
     size: [u32; 2],
-    sender: Sender<Pixel>,
-    receiver: Receiver<Pixel>,
+    sender: Sender<Tile>,
+    receiver: Receiver<Tile>,
 }
 
+/// Samples traced for a single pixel.
+#[derive(Clone, Copy, Debug)]
 pub struct Pixel {
-    pub position: [u32; 2],
-    pub color: [u8; 4],
+    /// Sum of the linear radiance of all samples.
+    pub radiance: Color,
+    pub samples: u32,
+    /// Samples traced, including the non-finite ones left out of `samples`.
+    pub traced: u32,
 }
 
 impl PathTracer {
@@ -32,94 +127,292 @@ impl PathTracer {
         }
     }
 
-    pub fn run(self, scene: Scene, n_samples: usize, max_bounces: u8) -> Receiver<Pixel> {
+    pub fn run(self, scene: Scene, settings: RenderSettings) -> RenderJob {
+        let PathTracer {
+            size,
+            sender,
+            receiver,
+        } = self;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
         // TODO: Make the allocated `Vec`s thread-local to avoid reallocating
-        std::thread::spawn(move || {
-            let area = self.size[0] * self.size[1];
-            let skip = (1..=self.size[0] / 3)
-                .reduce(|div, i| {
-                    if self.size[0] % i == 0 && self.size[1] % i == 0 {
-                        i
-                    } else {
-                        div
-                    }
+        let handle = std::thread::spawn(move || {
+            let cancelled = thread_cancelled;
+            let tiles = tile::layout(size, settings.tile_size, settings.tile_order);
+
+            let mut stats: Vec<Vec<PixelStats>> = tiles
+                .iter()
+                .map(|region| {
+                    vec![PixelStats::default(); (region.size[0] * region.size[1]) as usize]
                 })
-                .unwrap_or(1);
-
-            (0..area).par_bridge().for_each(|index| {
-                let scaled_i = index * skip;
-                let j = (scaled_i % area) + (scaled_i / area);
-
-                let y = j % self.size[1];
-                let x = j / self.size[1];
-
-                let position = [x, y];
-
-                let mut color = Color::from(Vec4::ZERO);
-                let mut last_luma = f32::INFINITY;
-                let mut i = 0;
-
-                for _ in 1..=n_samples {
-                    let u = ((x as f32 + fastrand::f32()) / self.size[0] as f32) * 2.0 - 1.0;
-                    let v = ((y as f32 + fastrand::f32()) / self.size[1] as f32) * 2.0 - 1.0;
-                    let ray = Ray::from_uv(&scene.camera, u, v);
-                    let new_color = ray.color(&scene, max_bounces);
-                    if new_color.inner.is_finite() {
-                        i += 1;
-                        color += new_color;
-                    } else {
-                        continue;
-                    }
-                    // Early-out based on luminance convergence
-                    if i % 64 == 0 {
-                        let luma = (&color / i as f32).approx_luminance();
-                        let delta = last_luma - luma;
-                        if delta.abs() <= f32::EPSILON * 10.0 {
-                            println!("early exit y: {y} n: {i}");
-                            break;
+                .collect();
+
+            // Traces up to `count` more samples through every pixel that still needs them.
+            let pass = |stats: &mut Vec<Vec<PixelStats>>, count: usize| {
+                // `par_bridge` hands out tiles roughly in order, unlike splitting a parallel
+                // iterator, so the image fills in following `tile_order`.
+                tiles
+                    .iter()
+                    .zip(stats.iter_mut())
+                    .par_bridge()
+                    .for_each(|(region, stats)| {
+                        let mut pixels = Vec::with_capacity(stats.len());
+                        let positions = (region.origin[1]..region.origin[1] + region.size[1])
+                            .flat_map(|y| {
+                                (region.origin[0]..region.origin[0] + region.size[0])
+                                    .map(move |x| [x, y])
+                            });
+                        for (position, stats) in positions.zip(stats.iter_mut()) {
+                            match trace_pixel(
+                                &scene, &settings, size, position, stats, count, &cancelled,
+                            ) {
+                                Some(pixel) => pixels.push(pixel),
+                                None => return,
+                            }
                         }
-                        last_luma = luma;
+                        sender
+                            .send(Tile {
+                                region: *region,
+                                pixels,
+                            })
+                            .ok();
+                    });
+            };
+
+            // Progressive renders trace 1 sample per pixel over the whole frame, then 2, 4 and so
+            // on, so a noisy preview of the full image is available right away.
+            if settings.progressive {
+                let (mut done, mut pass_samples) = (0, 1);
+                while done < settings.samples {
+                    let n = pass_samples.min(settings.samples - done);
+                    pass(&mut stats, n);
+                    done += n;
+                    pass_samples *= 2;
+                }
+            } else {
+                pass(&mut stats, settings.samples);
+            }
+
+            // Samples saved on pixels that converged early are shared between the others, in
+            // passes until they run out or every pixel converged.
+            if let Some(threshold) = settings.adaptive_threshold {
+                let budget = settings.samples * (size[0] * size[1]) as usize;
+                let max_samples = settings.samples * MAX_ADAPTIVE_FACTOR;
+                while !cancelled.load(Ordering::Relaxed) {
+                    let (spent, noisy) =
+                        stats
+                            .iter()
+                            .flatten()
+                            .fold((0, 0), |(spent, noisy), pixel| {
+                                let noisy = noisy
+                                    + (!pixel.converged(threshold)
+                                        && (pixel.traced as usize) < max_samples)
+                                        as usize;
+                                (spent + pixel.traced as usize, noisy)
+                            });
+                    let remaining = budget.saturating_sub(spent);
+                    if noisy == 0 || remaining < noisy {
+                        break;
                     }
+                    pass(&mut stats, (remaining / noisy).min(settings.samples));
                 }
+            }
 
-                // Gamma correction
-                let n = i as f32;
-                color = color / n;
-                color = Vec4::new(
-                    color.r().sqrt(),
-                    color.g().sqrt(),
-                    color.b().sqrt(),
-                    color.a(),
-                )
-                .into();
-
-                // convert to u8 range
-                let color = color.into_bytes();
-                self.sender.send(Pixel { position, color }).ok();
-            });
+            if cancelled.load(Ordering::Relaxed) {
+                RenderOutcome::Cancelled
+            } else {
+                RenderOutcome::Completed
+            }
         });
-        self.receiver
+        RenderJob::new(receiver, cancelled, handle)
+    }
+}
+
+/// Noisy pixels of adaptive renders take at most this many times [`RenderSettings::samples`].
+const MAX_ADAPTIVE_FACTOR: usize = 8;
+/// Samples traced through a pixel before its variance is trusted to decide it converged.
+const MIN_ADAPTIVE_SAMPLES: u32 = 16;
+
+/// Running mean and variance of the luminance of the samples of a pixel, using Welford's
+/// algorithm.
+#[derive(Clone, Copy, Debug, Default)]
+struct PixelStats {
+    /// Samples traced so far, including the ones that were discarded. Also the index of the next
+    /// sample.
+    traced: u32,
+    /// Samples that were kept.
+    count: u32,
+    mean: f32,
+    /// Sum of the squared differences to the mean.
+    m2: f32,
+}
+impl PixelStats {
+    #[inline(always)]
+    fn add(&mut self, luminance: f32) {
+        self.count += 1;
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    /// Returns `true` once the standard error of the mean is under `threshold` times the mean.
+    #[inline(always)]
+    fn converged(&self, threshold: f32) -> bool {
+        if self.count < MIN_ADAPTIVE_SAMPLES {
+            return false;
+        }
+        let variance = self.m2 / (self.count - 1) as f32;
+        (variance / self.count as f32).sqrt() <= threshold * self.mean
+    }
+}
+
+/// Traces up to `count` more samples through the pixel at `position`, continuing from the ones
+/// recorded in `stats`. Adaptive renders stop early once the pixel converged. Returns `None` if
+/// the render was cancelled before the pixel was finished.
+fn trace_pixel(
+    scene: &Scene,
+    settings: &RenderSettings,
+    size: [u32; 2],
+    position: [u32; 2],
+    stats: &mut PixelStats,
+    count: usize,
+    cancelled: &AtomicBool,
+) -> Option<Pixel> {
+    let [x, y] = position;
+    let mut color = Color::from(Vec4::ZERO);
+    let (mut i, mut traced) = (0u32, 0u32);
+    let mut sampler = settings.sampler.clone();
+
+    for _ in 0..count {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        if let Some(threshold) = settings.adaptive_threshold {
+            if stats.converged(threshold)
+                || stats.traced as usize >= settings.samples * MAX_ADAPTIVE_FACTOR
+            {
+                break;
+            }
+        }
+        sampler.start_sample(
+            settings.seed,
+            position,
+            stats.traced,
+            settings.samples as u32,
+        );
+        stats.traced += 1;
+        traced += 1;
+        let jitter = sampler.next_2d();
+        let u = ((x as f32 + jitter.x) / size[0] as f32) * 2.0 - 1.0;
+        let v = ((y as f32 + jitter.y) / size[1] as f32) * 2.0 - 1.0;
+        let ray = Ray::from_uv(&scene.camera, u, v);
+        let new_color = ray.color(scene, settings.max_bounces, sampler.as_mut());
+        if new_color.inner.is_finite() {
+            i += 1;
+            color += new_color;
+            stats.add(new_color.approx_luminance());
+        }
+    }
+
+    Some(Pixel {
+        radiance: color,
+        samples: i,
+        traced,
+    })
+}
+
+/// Options controlling how [`PathTracer::run`] renders a scene.
+#[derive(Clone)]
+pub struct RenderSettings {
+    /// Number of samples traced per pixel.
+    pub samples: usize,
+    /// Maximum number of rays in a path. Paths are otherwise only ended by Russian roulette.
+    pub max_bounces: Option<u32>,
+    /// Render the whole frame in passes of 1, 2, 4... samples per pixel, sending every pixel after
+    /// each pass, instead of finishing pixels one at a time.
+    pub progressive: bool,
+    /// Width and height of the square tiles the image is split into.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Seed of the random numbers used for sampling. Renders of the same scene with the same
+    /// settings are identical, whatever the number of threads.
+    pub seed: u64,
+    /// Generates the sample values of each pixel, see [`sampler::all`].
+    pub sampler: Box<dyn Sampler>,
+    /// Stop sampling pixels once the standard error of their mean luminance is under this
+    /// fraction of it, and spend the samples saved on noisier pixels. `samples` is then the
+    /// average number of samples per pixel.
+    pub adaptive_threshold: Option<f32>,
+}
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples: 32,
+            max_bounces: None,
+            progressive: false,
+            tile_size: 16,
+            tile_order: TileOrder::default(),
+            seed: 0,
+            sampler: Box::new(sampler::Sobol::default()),
+            adaptive_threshold: None,
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct Camera {
+    pub(crate) position: Vec3,
+    pub(crate) target: Vec3,
+    pub(crate) up: Vec3,
+    /// Vertical field of view, in radians.
+    pub(crate) vertical_fov: f32,
+    pub(crate) aspect_ratio: f32,
     pub(crate) transform: Mat4,
     pub(crate) inv_transform: Mat4,
     pub(crate) inv_projection: Mat4,
 }
 impl Camera {
-    /// Computes a ray in world space given the x and y coordinates of the target.
+    /// Builds a camera at `position` looking at `target`, with a vertical field of view given in
+    /// radians.
+    pub fn new(
+        position: Vec3,
+        target: Vec3,
+        up: Vec3,
+        vertical_fov: f32,
+        aspect_ratio: f32,
+    ) -> Self {
+        let mut camera = Self {
+            position,
+            target,
+            up,
+            vertical_fov,
+            aspect_ratio,
+            transform: Mat4::IDENTITY,
+            inv_transform: Mat4::IDENTITY,
+            inv_projection: Mat4::IDENTITY,
+        };
+        camera.update_matrices();
+        camera
+    }
+
+    /// A camera at the origin looking down -Z, with a 90° vertical field of view.
     #[inline(always)]
     pub fn from_aspect_ratio(aspect_ratio: f32) -> Self {
-        let inv_projection =
-            Mat4::perspective_infinite_reverse_rh(PI / 2.0, aspect_ratio, 1.0).inverse();
-        let transform = Mat4::look_at_rh(Vec3::ZERO, -Vec3::Z, Vec3::Y);
-        Self {
-            inv_transform: transform.inverse(),
-            transform,
-            inv_projection,
-        }
+        Self::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, PI / 2.0, aspect_ratio)
+    }
+
+    /// Changes the aspect ratio of the camera, keeping its placement and vertical field of view.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.update_matrices();
+    }
+
+    fn update_matrices(&mut self) {
+        self.inv_projection =
+            Mat4::perspective_infinite_reverse_rh(self.vertical_fov, self.aspect_ratio, 1.0)
+                .inverse();
+        self.transform = Mat4::look_at_rh(self.position, self.target, self.up);
+        self.inv_transform = self.transform.inverse();
     }
 }
 
@@ -138,6 +431,15 @@ impl Sdf for SdfObject {
     fn normal(&self, ray_position: Vec3A) -> Vec3A {
         self.isosurface.normal(ray_position)
     }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        self.isosurface.descriptor()
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        self.isosurface.bounds()
+    }
 }
 impl SdfObject {
     pub fn new<S, M>(isosurface: S, material: Arc<M>) -> Self
@@ -150,42 +452,72 @@ impl SdfObject {
             material,
         }
     }
+
+    /// Builds an object from an already type-erased surface and material.
+    pub fn from_boxed(isosurface: Box<dyn Sdf>, material: Arc<dyn Material>) -> Self {
+        Self {
+            isosurface,
+            material,
+        }
+    }
+
+    #[inline(always)]
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
 }
 
 clone_trait_object!(Sdf);
 
+impl Sdf for Box<dyn Sdf> {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        (**self).distance(ray_position)
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        (**self).normal(ray_position)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        (**self).descriptor()
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        (**self).bounds()
+    }
+}
+
 #[derive(Clone)]
 pub struct Scene {
     pub camera: Camera,
     pub objects: Vec<SdfObject>,
-    pub materials: Vec<Arc<dyn Material>>,
+    /// Materials by name, as referenced from scene files.
+    pub materials: BTreeMap<String, Arc<dyn Material>>,
+    /// Punctual lights, in addition to the emissive objects of the scene.
+    pub lights: Vec<Light>,
+    /// What rays escaping the scene see, and are lit by.
+    pub background: Background,
 }
-
 pub trait Sdf: Send + Sync + DynClone {
     fn distance(&self, ray_position: Vec3A) -> f32;
     fn normal(&self, ray_position: Vec3A) -> Vec3A;
-}
-
-#[derive(Clone)]
-pub struct Sphere {
-    /// Position and radius packed into a Vec4
-    pos_rad: Vec4,
-}
-impl Sphere {
-    pub fn new(position: Vec3, radius: f32) -> Self {
-        Sphere {
-            pos_rad: position.extend(radius),
-        }
+    /// Describes this surface for the scene file format. Surfaces that can't be written to a scene
+    /// file return `None`.
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        None
     }
-}
-impl Sdf for Sphere {
-    #[inline(always)]
-    fn distance(&self, ray_position: Vec3A) -> f32 {
-        ray_position.distance(self.pos_rad.xyz().into()) - self.pos_rad.w
+    /// A sphere enclosing the surface, used to aim shadow rays at emissive objects. Surfaces
+    /// that can't be bounded return `None`, and are only found by rays bouncing into them.
+    fn bounds(&self) -> Option<BoundingSphere> {
+        None
     }
+}
 
-    #[inline(always)]
-    fn normal(&self, ray_position: Vec3A) -> Vec3A {
-        (ray_position - Vec3A::from(self.pos_rad.xyz())).normalize()
-    }
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: Vec3A,
+    pub radius: f32,
 }
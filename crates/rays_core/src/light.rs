@@ -0,0 +1,286 @@
+//! Light sources, and sampling directions towards them for next event estimation.
+//!
+//! Scenes are lit by emissive objects, the punctual [`Light`]s in [`Scene::lights`], and the
+//! [`Scene::background`].
+//! Emissive objects are sampled through their [`BoundingSphere`], by picking a direction in the
+//! cone it covers as seen from the shaded point. Directions in the cone that miss the object
+//! itself are wasted, but keep the estimate unbiased for any shape.
+
+use std::f32::consts::PI;
+
+use glam::{Vec3, Vec3A};
+
+use crate::{
+    material::tangent_frame, sampler::Sampler, scene::LightDescriptor, BoundingSphere, Scene, Sdf,
+};
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use glam::{Vec3, Vec3A};
+
+    use crate::{
+        environment::{Background, Gradient},
+        light::{pdf, sample},
+        material::Emissive,
+        sampler::Independent,
+        sdf::Sphere,
+        Camera, Scene, SdfObject,
+    };
+
+    #[test]
+    fn samples_aim_at_the_light() {
+        let light = Emissive::new(Vec3::ONE.extend(1.0).into(), 1.0);
+        let scene = Scene {
+            camera: Camera::from_aspect_ratio(1.0),
+            objects: vec![SdfObject::new(
+                Sphere::new(Vec3::new(0.0, 3.0, 0.0), 1.0),
+                Arc::new(light),
+            )],
+            materials: Default::default(),
+            lights: Vec::new(),
+            background: Background {
+                environment: Arc::new(Gradient),
+                ..Default::default()
+            },
+        };
+        let mut sampler = Independent::default();
+        for _ in 0..1000 {
+            let sample = sample(&scene, Vec3A::ZERO, &mut sampler).unwrap();
+            // Every direction in the cone of a sphere hits it
+            let to_center = Vec3A::new(0.0, 3.0, 0.0);
+            let closest = to_center - sample.direction * to_center.dot(sample.direction);
+            assert!(closest.length() <= 1.0 + 1e-4);
+            assert!((sample.pdf - pdf(&scene, Vec3A::ZERO, sample.direction)).abs() < 1e-4);
+        }
+        assert_eq!(pdf(&scene, Vec3A::ZERO, -Vec3A::Y), 0.0);
+        // Points inside the bounds of a light can't sample it
+        assert!(sample(&scene, Vec3A::new(0.0, 3.5, 0.0), &mut sampler).is_none());
+    }
+}
+
+/// A light without any geometry, which can't be seen or hit by rays, only shine on surfaces.
+///
+/// Intensities are in physical units: scene radiance is taken to be in W/(sr·m²).
+#[derive(Clone, Debug)]
+pub enum Light {
+    /// Light emitted from a single point, in every direction.
+    Point {
+        position: Vec3A,
+        color: Vec3,
+        /// Radiant intensity, in W/sr.
+        intensity: f32,
+    },
+    /// A point light shining in a cone around `direction`, fading out from `inner_angle` to
+    /// `outer_angle`.
+    Spot {
+        position: Vec3A,
+        /// Normalized direction the light shines towards.
+        direction: Vec3A,
+        color: Vec3,
+        /// Radiant intensity at the center of the cone, in W/sr.
+        intensity: f32,
+        /// Half angle of the fully lit part of the cone, in radians.
+        inner_angle: f32,
+        /// Half angle of the cone, outside of which the light is off, in radians.
+        outer_angle: f32,
+    },
+    /// Parallel light coming from infinitely far away, like the sun.
+    Directional {
+        /// Normalized direction the light travels in.
+        direction: Vec3A,
+        color: Vec3,
+        /// Irradiance on a surface facing the light, in W/m².
+        irradiance: f32,
+    },
+}
+
+/// Light arriving at a point from a [`Light`].
+#[derive(Clone, Copy, Debug)]
+pub struct Incident {
+    /// Normalized direction towards the light.
+    pub direction: Vec3A,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f32,
+    /// Irradiance on a surface facing the light.
+    pub irradiance: Vec3,
+}
+
+impl Light {
+    /// Light arriving at `position`, ignoring occlusion. Returns `None` if the light doesn't
+    /// reach it.
+    pub fn illuminate(&self, position: Vec3A) -> Option<Incident> {
+        match *self {
+            Light::Point {
+                position: light_position,
+                color,
+                intensity,
+            } => {
+                let to_light = light_position - position;
+                let distance = to_light.length();
+                Some(Incident {
+                    direction: to_light / distance,
+                    distance,
+                    irradiance: color * intensity / (distance * distance),
+                })
+            }
+            Light::Spot {
+                position: light_position,
+                direction,
+                color,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                let to_light = light_position - position;
+                let distance = to_light.length();
+                let to_light = to_light / distance;
+                // Smooth falloff between the two cones
+                let (cos_inner, cos_outer) = (inner_angle.cos(), outer_angle.cos());
+                let cos = (-to_light).dot(direction);
+                if cos <= cos_outer {
+                    return None;
+                }
+                let t = ((cos - cos_outer) / (cos_inner - cos_outer).max(1e-6)).min(1.0);
+                let falloff = t * t * (3.0 - 2.0 * t);
+                Some(Incident {
+                    direction: to_light,
+                    distance,
+                    irradiance: color * intensity * falloff / (distance * distance),
+                })
+            }
+            Light::Directional {
+                direction,
+                color,
+                irradiance,
+            } => Some(Incident {
+                direction: -direction,
+                distance: f32::INFINITY,
+                irradiance: color * irradiance,
+            }),
+        }
+    }
+
+    /// Describes this light for the scene file format.
+    pub fn descriptor(&self) -> LightDescriptor {
+        match *self {
+            Light::Point {
+                position,
+                color,
+                intensity,
+            } => LightDescriptor::Point {
+                position: position.into(),
+                color,
+                intensity,
+            },
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => LightDescriptor::Spot {
+                position: position.into(),
+                direction: direction.into(),
+                color,
+                intensity,
+                inner_angle: inner_angle.to_degrees(),
+                outer_angle: outer_angle.to_degrees(),
+            },
+            Light::Directional {
+                direction,
+                color,
+                irradiance,
+            } => LightDescriptor::Directional {
+                direction: direction.into(),
+                color,
+                irradiance,
+            },
+        }
+    }
+}
+
+/// A direction sampled by [`sample`].
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    /// Normalized direction towards a light.
+    pub direction: Vec3A,
+    /// Probability density of sampling `direction`, per unit solid angle.
+    pub pdf: f32,
+}
+
+/// Bounds of the emissive objects of the scene that can be sampled.
+fn emitters(scene: &Scene) -> impl Iterator<Item = BoundingSphere> + '_ {
+    scene
+        .objects
+        .iter()
+        .filter(|object| object.material().is_emissive())
+        .filter_map(|object| object.bounds())
+}
+
+/// Axis and `1 - cos` of the half angle of the cone covered by `bounds` as seen from `position`,
+/// or `None` if `position` is inside the bounds.
+#[inline(always)]
+fn cone(bounds: BoundingSphere, position: Vec3A) -> Option<(Vec3A, f32)> {
+    let to_center = bounds.center - position;
+    let distance_sq = to_center.length_squared();
+    let sin2_max = bounds.radius * bounds.radius / distance_sq;
+    if sin2_max >= 1.0 {
+        return None;
+    }
+    // Written to stay precise for small and distant lights, where the cosine is close to 1.0
+    let one_minus_cos = sin2_max / (1.0 + (1.0 - sin2_max).sqrt());
+    Some((to_center / distance_sq.sqrt(), one_minus_cos))
+}
+
+/// Number of light sources sampled by [`sample`]: the emissive objects, plus the background if
+/// it can be importance sampled.
+fn light_count(scene: &Scene) -> usize {
+    emitters(scene).count() + scene.background.is_sampled() as usize
+}
+
+/// Samples a direction from `position` towards one of the emissive objects of `scene` or its
+/// background, picked uniformly. Returns `None` if there are no lights to sample.
+pub fn sample(scene: &Scene, position: Vec3A, sampler: &mut dyn Sampler) -> Option<LightSample> {
+    let count = light_count(scene);
+    if count == 0 {
+        return None;
+    }
+    let pick = ((sampler.next_1d() * count as f32) as usize).min(count - 1);
+    let direction = match emitters(scene).nth(pick) {
+        Some(bounds) => {
+            let (axis, one_minus_cos) = cone(bounds, position)?;
+            let u = sampler.next_2d();
+            let cos_theta = 1.0 - u.x * one_minus_cos;
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u.y;
+            let (tangent, bitangent) = tangent_frame(axis);
+            (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + axis * cos_theta
+        }
+        None => scene.background.sample(sampler)?.0,
+    };
+
+    // Directions right on the edge of the cone can fall outside of it through rounding
+    let pdf = pdf(scene, position, direction);
+    (pdf > 0.0).then_some(LightSample { direction, pdf })
+}
+
+/// Probability density of [`sample`] returning `direction` from `position`. Lights can overlap,
+/// so this sums the density of each of them.
+pub fn pdf(scene: &Scene, position: Vec3A, direction: Vec3A) -> f32 {
+    let count = light_count(scene);
+    if count == 0 {
+        return 0.0;
+    }
+    let mut density: f32 = emitters(scene)
+        .filter_map(|bounds| cone(bounds, position))
+        .filter(|&(axis, one_minus_cos)| direction.dot(axis) >= 1.0 - one_minus_cos)
+        .map(|(_, one_minus_cos)| 1.0 / (2.0 * PI * one_minus_cos))
+        .sum();
+    if scene.background.is_sampled() {
+        density += scene.background.pdf(direction);
+    }
+    density / count as f32
+}
@@ -1,17 +1,178 @@
+use std::f32::consts::PI;
+
 use dyn_clone::{clone_trait_object, DynClone};
-use glam::Vec3A;
+use glam::{Vec2, Vec3, Vec3A, Vec4};
+
+use crate::{color::Color, ray::RayHit, sampler::Sampler, scene::MaterialDescriptor};
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec3A};
+
+    use crate::{
+        material::{Lambertian, Material, Metal, Principled},
+        ray::{Ray, RayHit},
+        sampler::Independent,
+    };
+
+    fn hit(angle: f32) -> RayHit {
+        RayHit {
+            in_dir: Ray {
+                origin: Vec3A::ZERO,
+                direction: Vec3A::new(angle.sin(), -angle.cos(), 0.0),
+            },
+            position: Vec3A::ZERO,
+            normal: Vec3A::Y,
+            front_face: true,
+        }
+    }
+
+    /// Mean weight of rays scattered off a surface lit from every direction by white light. A
+    /// surface can't reflect more light than it receives, so this is at most 1.0.
+    fn furnace(material: &dyn Material, angle: f32) -> f32 {
+        let hit = hit(angle);
+        let mut sampler = Independent::default();
+        let n = 100_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            if let Some(sample) = material.scatter(&hit, &mut sampler) {
+                assert!(sample.weight.inner.is_finite() && sample.pdf > 0.0);
+                sum += sample.weight.approx_luminance();
+            }
+        }
+        sum / n as f32
+    }
 
-use crate::{
-    color::Color,
-    ray::{self, RayHit},
-};
+    #[test]
+    fn microfacet_materials_conserve_energy() {
+        let materials: [Box<dyn Material>; 3] = [
+            Box::new(Metal::new(Vec3::ONE.extend(1.0).into(), 0.5)),
+            Box::new(Principled::new(
+                Vec3::ONE.extend(1.0).into(),
+                0.0,
+                0.3,
+                0.5,
+                1.0,
+            )),
+            Box::new(Principled::new(
+                Vec3::splat(0.8).extend(1.0).into(),
+                1.0,
+                0.8,
+                0.5,
+                0.0,
+            )),
+        ];
+        for material in &materials {
+            for angle in [0.0, 0.8, 1.4] {
+                let albedo = furnace(material.as_ref(), angle);
+                assert!(albedo > 0.3 && albedo < 1.05, "{albedo} at {angle}");
+            }
+        }
+    }
+
+    #[test]
+    fn eval_matches_scatter() {
+        let materials: [Box<dyn Material>; 3] = [
+            Box::new(Lambertian::new(Vec3::splat(0.5).extend(1.0).into())),
+            Box::new(Metal::new(Vec3::ONE.extend(1.0).into(), 0.4)),
+            Box::new(Principled::new(
+                Vec3::new(0.8, 0.2, 0.1).extend(1.0).into(),
+                0.3,
+                0.5,
+                0.5,
+                0.5,
+            )),
+        ];
+        let hit = hit(0.6);
+        let mut sampler = Independent::default();
+        for material in &materials {
+            for _ in 0..1000 {
+                let Some(sample) = material.scatter(&hit, &mut sampler) else {
+                    continue;
+                };
+                let (value, pdf) = material.eval(&hit, sample.direction);
+                assert!(!sample.is_specular);
+                assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf);
+                let weight = value / pdf;
+                assert!((weight.rgb() - sample.weight.rgb()).abs().max_element() < 1e-3);
+            }
+        }
+    }
+    #[test]
+    fn views_below_the_surface_scatter_nothing() {
+        let materials: [Box<dyn Material>; 2] = [
+            Box::new(Metal::new(Vec3::ONE.extend(1.0).into(), 0.4)),
+            Box::new(Principled::new(
+                Vec3::ONE.extend(1.0).into(),
+                1.0,
+                0.4,
+                0.5,
+                0.0,
+            )),
+        ];
+        // Grazing rays can stop marching on the far side of the normal
+        let hit = hit(1.6);
+        let mut sampler = Independent::default();
+        for material in &materials {
+            for _ in 0..100 {
+                assert!(material.scatter(&hit, &mut sampler).is_none());
+            }
+            let (value, pdf) = material.eval(&hit, Vec3A::new(0.3, 0.9, 0.1).normalize());
+            assert!(value.rgb() == Vec3::ZERO && pdf == 0.0);
+        }
+    }
+}
+
+/// A direction sampled by [`Material::scatter`], with the weight of the light arriving from it.
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterSample {
+    /// Normalized direction of the scattered ray.
+    pub direction: Vec3A,
+    /// The BRDF times the cosine of `direction` with the normal, divided by `pdf`: the factor
+    /// applied to the light arriving along the scattered ray.
+    pub weight: Color,
+    /// Probability density of sampling `direction`, per unit solid angle. Specular samples have no
+    /// density, and use [`f32::INFINITY`].
+    pub pdf: f32,
+    /// `true` for perfect reflection or refraction, where `direction` is the only direction light
+    /// scatters towards, so [`Material::eval`] can't be used for it.
+    pub is_specular: bool,
+}
 
 pub trait Material: Send + Sync + DynClone {
-    /// Returns the scatter direction as a result of a ray hitting the surface of the material. This
-    /// vector should **not be normalized**, as this is handled in the [`ray::Ray`]'s color
-    /// function.
-    fn scatter(&self, hit: &RayHit) -> Vec3A;
-    fn attenuation(&self) -> &Color;
+    /// Samples the direction a ray hitting the surface of the material scatters towards.
+    ///
+    /// The direction may point away from [`RayHit::normal`] to transmit the ray through the
+    /// surface, in which case the ray continues inside the object, and [`RayHit::front_face`] is
+    /// `false` when it reaches the surface again from the inside.
+    ///
+    /// Returns `None` if the ray is absorbed.
+    fn scatter(&self, hit: &RayHit, sampler: &mut dyn Sampler) -> Option<ScatterSample>;
+    /// Evaluates light arriving from `direction` and scattered back along the incoming ray.
+    /// Returns the BSDF times the cosine of `direction` with the normal, and the pdf of
+    /// [`Material::scatter`] sampling `direction`.
+    ///
+    /// Specular materials only scatter towards a single direction, and return zero for both.
+    #[inline(always)]
+    fn eval(&self, _hit: &RayHit, _direction: Vec3A) -> (Color, f32) {
+        (Color::from(Vec4::ZERO), 0.0)
+    }
+    /// Light emitted by the surface towards the incoming ray. The alpha channel is ignored.
+    #[inline(always)]
+    fn emitted(&self, _hit: &RayHit) -> Color {
+        Color::from(Vec4::ZERO)
+    }
+    /// `true` if [`Material::emitted`] can return any light, so objects using this material are
+    /// sampled as light sources.
+    #[inline(always)]
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    /// Describes this material for the scene file format. Materials that can't be written to a
+    /// scene file return `None`.
+    fn descriptor(&self) -> Option<MaterialDescriptor> {
+        None
+    }
 }
 
 // Implements Clone for the boxed trait objects
@@ -30,36 +191,464 @@ impl Lambertian {
 }
 impl Material for Lambertian {
     #[inline(always)]
-    fn scatter(&self, hit: &RayHit) -> Vec3A {
-        hit.normal + ray::rand_on_unit_sphere()
+    fn scatter(&self, hit: &RayHit, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        let direction = sample_cosine(hit.normal, sampler.next_2d());
+        Some(ScatterSample {
+            direction,
+            // The cosine and 1/π of the BRDF cancel out with the pdf
+            weight: self.albedo,
+            pdf: direction.dot(hit.normal).max(0.0) / PI,
+            is_specular: false,
+        })
     }
 
     #[inline(always)]
-    fn attenuation(&self) -> &Color {
-        &self.albedo
+    fn eval(&self, hit: &RayHit, direction: Vec3A) -> (Color, f32) {
+        let cos_l = direction.dot(hit.normal).max(0.0);
+        (self.albedo * (cos_l / PI), cos_l / PI)
+    }
+
+    fn descriptor(&self) -> Option<MaterialDescriptor> {
+        Some(MaterialDescriptor::Lambertian {
+            albedo: self.albedo.rgb(),
+        })
     }
 }
 
+/// A conductor, reflecting light around the mirror direction with a GGX microfacet distribution.
 #[derive(Clone, Debug)]
 pub struct Metal {
     albedo: Color,
+    /// How far reflections are scattered around the mirror direction, from 0.0 (a perfect mirror)
+    /// to 1.0.
+    roughness: f32,
 }
 
 impl Metal {
     #[inline(always)]
-    pub fn new(albedo: Color) -> Metal {
-        Metal { albedo }
+    pub fn new(albedo: Color, roughness: f32) -> Metal {
+        Metal {
+            albedo,
+            roughness: roughness.clamp(0.0, 1.0),
+        }
     }
 }
 
 impl Material for Metal {
     #[inline(always)]
-    fn scatter(&self, hit: &RayHit) -> Vec3A {
-        hit.in_dir.reflect(hit.normal)
+    fn scatter(&self, hit: &RayHit, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        if self.roughness == 0.0 {
+            return Some(ScatterSample {
+                direction: hit.in_dir.reflect(hit.normal).normalize(),
+                weight: self.albedo,
+                pdf: f32::INFINITY,
+                is_specular: true,
+            });
+        }
+        let view = -hit.in_dir.direction.normalize();
+        if view.dot(hit.normal) <= 0.0 {
+            return None;
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        let direction = ggx.sample(hit.normal, view, sampler.next_2d());
+        // Rough reflections at grazing angles can be pushed below the surface, where they would
+        // start marching inside the object. Those rays are absorbed instead.
+        let cos_l = direction.dot(hit.normal);
+        if cos_l <= 0.0 {
+            return None;
+        }
+        let pdf = ggx.pdf(hit.normal, view, direction);
+        let brdf = ggx.eval(hit.normal, view, direction, self.albedo.rgb());
+        Some(ScatterSample {
+            direction,
+            weight: Color::from((brdf * cos_l / pdf).extend(1.0)),
+            pdf,
+            is_specular: false,
+        })
+    }
+
+    #[inline(always)]
+    fn eval(&self, hit: &RayHit, direction: Vec3A) -> (Color, f32) {
+        if self.roughness == 0.0 {
+            return (Color::from(Vec4::ZERO), 0.0);
+        }
+        let view = -hit.in_dir.direction.normalize();
+        let cos_l = direction.dot(hit.normal);
+        if view.dot(hit.normal) <= 0.0 || cos_l <= 0.0 {
+            return (Color::from(Vec4::ZERO), 0.0);
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        let brdf = ggx.eval(hit.normal, view, direction, self.albedo.rgb());
+        (
+            Color::from((brdf * cos_l).extend(1.0)),
+            ggx.pdf(hit.normal, view, direction),
+        )
+    }
+
+    fn descriptor(&self) -> Option<MaterialDescriptor> {
+        Some(MaterialDescriptor::Metal {
+            albedo: self.albedo.rgb(),
+            roughness: self.roughness,
+        })
+    }
+}
+
+/// A clear material like glass or water, which both reflects and refracts light.
+#[derive(Clone, Debug)]
+pub struct Dielectric {
+    /// Index of refraction of the material, relative to the surrounding air.
+    ior: f32,
+    tint: Color,
+}
+
+impl Dielectric {
+    #[inline(always)]
+    pub fn new(ior: f32, tint: Color) -> Dielectric {
+        Dielectric { ior, tint }
+    }
+}
+
+impl Material for Dielectric {
+    #[inline(always)]
+    fn scatter(&self, hit: &RayHit, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        // Ratio of the index of refraction the ray travels through to the one it enters
+        let eta = if hit.front_face {
+            1.0 / self.ior
+        } else {
+            self.ior
+        };
+        let direction = hit.in_dir.direction.normalize();
+        let cos_i = (-direction).dot(hit.normal).min(1.0);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        let reflected = direction - 2.0 * direction.dot(hit.normal) * hit.normal;
+
+        // Total internal reflection
+        let direction = if sin2_t >= 1.0 {
+            reflected
+        } else {
+            let cos_t = (1.0 - sin2_t).sqrt();
+            if sampler.next_1d() < fresnel(cos_i, cos_t, eta) {
+                reflected
+            } else {
+                (eta * direction + (eta * cos_i - cos_t) * hit.normal).normalize()
+            }
+        };
+        Some(ScatterSample {
+            direction,
+            weight: self.tint,
+            pdf: f32::INFINITY,
+            is_specular: true,
+        })
+    }
+
+    fn descriptor(&self) -> Option<MaterialDescriptor> {
+        Some(MaterialDescriptor::Dielectric {
+            ior: self.ior,
+            tint: self.tint.rgb(),
+        })
+    }
+}
+
+/// A light source: a surface that emits light from its outside, and absorbs any light that hits
+/// it.
+#[derive(Clone, Debug)]
+pub struct Emissive {
+    color: Color,
+    intensity: f32,
+}
+
+impl Emissive {
+    #[inline(always)]
+    pub fn new(color: Color, intensity: f32) -> Emissive {
+        Emissive { color, intensity }
+    }
+}
+
+impl Material for Emissive {
+    #[inline(always)]
+    fn scatter(&self, _hit: &RayHit, _sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        None
+    }
+
+    #[inline(always)]
+    fn emitted(&self, hit: &RayHit) -> Color {
+        if hit.front_face {
+            Color::from((self.color.rgb() * self.intensity).extend(0.0))
+        } else {
+            Color::from(Vec4::ZERO)
+        }
+    }
+
+    #[inline(always)]
+    fn is_emissive(&self) -> bool {
+        self.intensity > 0.0 && self.color.rgb().max_element() > 0.0
+    }
+
+    fn descriptor(&self) -> Option<MaterialDescriptor> {
+        Some(MaterialDescriptor::Emissive {
+            color: self.color.rgb(),
+            intensity: self.intensity,
+        })
+    }
+}
+
+/// A physically based material covering most opaque surfaces, in the spirit of Disney's
+/// principled BRDF: a diffuse base, a GGX specular layer, and an optional clear coat on top.
+#[derive(Clone, Debug)]
+pub struct Principled {
+    base_color: Color,
+    /// Blends between a dielectric (0.0) and a metal (1.0) tinted by `base_color`.
+    metallic: f32,
+    roughness: f32,
+    /// Strength of the specular reflection of dielectrics. 0.5 is a reflectance of 4%, typical of
+    /// most materials.
+    specular: f32,
+    /// Strength of a second, glossy and uncolored specular layer, like varnish or car paint.
+    clearcoat: f32,
+}
+
+impl Principled {
+    /// Roughness of the clear coat layer.
+    const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+    #[inline(always)]
+    pub fn new(
+        base_color: Color,
+        metallic: f32,
+        roughness: f32,
+        specular: f32,
+        clearcoat: f32,
+    ) -> Principled {
+        Principled {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            specular: specular.clamp(0.0, 1.0),
+            clearcoat: clearcoat.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Probabilities of sampling the diffuse and specular lobes. The clear coat takes the rest.
+    #[inline(always)]
+    fn lobe_probabilities(&self) -> (f32, f32) {
+        let diffuse = 1.0 - self.metallic;
+        let specular = 1.0;
+        let clearcoat = 0.25 * self.clearcoat;
+        let total = diffuse + specular + clearcoat;
+        (diffuse / total, specular / total)
+    }
+
+    /// The BRDF and the pdf of [`Material::scatter`] for light arriving from `light` and leaving
+    /// towards `view`.
+    #[inline(always)]
+    fn evaluate(&self, normal: Vec3A, view: Vec3A, light: Vec3A) -> (Vec3, f32) {
+        let base_color = self.base_color.rgb();
+        let specular = Ggx::from_roughness(self.roughness);
+        let clearcoat = Ggx::from_roughness(Self::CLEARCOAT_ROUGHNESS);
+        let f0 = Vec3::splat(0.08 * self.specular).lerp(base_color, self.metallic);
+
+        // Light reflected by a layer doesn't reach the layers below it
+        let cos_v = view.dot(normal);
+        let diffuse = (1.0 - self.metallic)
+            * (1.0 - schlick(Vec3::splat(0.08 * self.specular), cos_v))
+            * base_color
+            / PI;
+        let coat_strength = 0.25 * self.clearcoat;
+        let coat_transmission = 1.0 - coat_strength * schlick(Vec3::splat(0.04), cos_v);
+        let brdf = coat_transmission * (diffuse + specular.eval(normal, view, light, f0))
+            + coat_strength * clearcoat.eval(normal, view, light, Vec3::splat(0.04));
+
+        let (p_diffuse, p_specular) = self.lobe_probabilities();
+        let pdf = p_diffuse * light.dot(normal).max(0.0) / PI
+            + p_specular * specular.pdf(normal, view, light)
+            + (1.0 - p_diffuse - p_specular) * clearcoat.pdf(normal, view, light);
+        (brdf, pdf)
+    }
+}
+
+impl Material for Principled {
+    #[inline(always)]
+    fn scatter(&self, hit: &RayHit, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        let view = -hit.in_dir.direction.normalize();
+        if view.dot(hit.normal) <= 0.0 {
+            return None;
+        }
+        let (p_diffuse, p_specular) = self.lobe_probabilities();
+        let lobe = sampler.next_1d();
+        let u = sampler.next_2d();
+        let direction = if lobe < p_diffuse {
+            sample_cosine(hit.normal, u)
+        } else if lobe < p_diffuse + p_specular {
+            Ggx::from_roughness(self.roughness).sample(hit.normal, view, u)
+        } else {
+            Ggx::from_roughness(Self::CLEARCOAT_ROUGHNESS).sample(hit.normal, view, u)
+        };
+        let cos_l = direction.dot(hit.normal);
+        if cos_l <= 0.0 {
+            return None;
+        }
+        // The pdf covers all lobes, so any of them could have produced this direction
+        let (brdf, pdf) = self.evaluate(hit.normal, view, direction);
+        Some(ScatterSample {
+            direction,
+            weight: Color::from((brdf * cos_l / pdf).extend(1.0)),
+            pdf,
+            is_specular: false,
+        })
+    }
+
+    #[inline(always)]
+    fn eval(&self, hit: &RayHit, direction: Vec3A) -> (Color, f32) {
+        let view = -hit.in_dir.direction.normalize();
+        let cos_l = direction.dot(hit.normal);
+        if view.dot(hit.normal) <= 0.0 || cos_l <= 0.0 {
+            return (Color::from(Vec4::ZERO), 0.0);
+        }
+        let (brdf, pdf) = self.evaluate(hit.normal, view, direction);
+        (Color::from((brdf * cos_l).extend(1.0)), pdf)
+    }
+
+    fn descriptor(&self) -> Option<MaterialDescriptor> {
+        Some(MaterialDescriptor::Principled {
+            base_color: self.base_color.rgb(),
+            metallic: self.metallic,
+            roughness: self.roughness,
+            specular: self.specular,
+            clearcoat: self.clearcoat,
+        })
+    }
+}
+
+/// Fraction of unpolarized light reflected at the boundary between two dielectrics, given the
+/// cosines of the incident and transmitted angles and the ratio of their refractive indices.
+#[inline(always)]
+fn fresnel(cos_i: f32, cos_t: f32, eta: f32) -> f32 {
+    let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_s * r_s + r_p * r_p) / 2.0
+}
+
+/// Schlick's approximation of the Fresnel reflectance, given the reflectance at normal incidence.
+#[inline(always)]
+fn schlick(f0: Vec3, cos: f32) -> Vec3 {
+    f0 + (1.0 - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+/// A cosine weighted direction in the hemisphere around `normal`, from two uniform numbers.
+#[inline(always)]
+fn sample_cosine(normal: Vec3A, u: Vec2) -> Vec3A {
+    // Uniform on the unit disk, projected up onto the hemisphere
+    let r = u.x.sqrt();
+    let phi = 2.0 * PI * u.y;
+    let (tangent, bitangent) = tangent_frame(normal);
+    (tangent * phi.cos() + bitangent * phi.sin()) * r + normal * (1.0 - u.x).max(0.0).sqrt()
+}
+
+/// Tangent and bitangent forming an orthonormal basis with the unit vector `normal`, from "Building
+/// an Orthonormal Basis, Revisited" by Duff et al.
+#[inline(always)]
+pub(crate) fn tangent_frame(normal: Vec3A) -> (Vec3A, Vec3A) {
+    let sign = 1f32.copysign(normal.z);
+    let a = -1.0 / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    (
+        Vec3A::new(
+            1.0 + sign * normal.x * normal.x * a,
+            sign * b,
+            -sign * normal.x,
+        ),
+        Vec3A::new(b, sign + normal.y * normal.y * a, -normal.y),
+    )
+}
+
+/// The GGX (Trowbridge-Reitz) microfacet distribution, with the separable Smith shadowing term.
+#[derive(Clone, Copy, Debug)]
+struct Ggx {
+    alpha: f32,
+}
+
+impl Ggx {
+    /// Uses the common `alpha = roughness²` remapping, which makes roughness perceptually linear.
+    /// Very low values are clamped to keep the distribution finite.
+    #[inline(always)]
+    fn from_roughness(roughness: f32) -> Ggx {
+        Ggx {
+            alpha: (roughness * roughness).max(1e-3),
+        }
+    }
+
+    /// Density of microfacet normals at an angle with cosine `cos_h` to the surface normal.
+    #[inline(always)]
+    fn d(&self, cos_h: f32) -> f32 {
+        let a2 = self.alpha * self.alpha;
+        let t = cos_h * cos_h * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    /// Fraction of microfacets visible from a direction with cosine `cos` to the surface normal.
+    #[inline(always)]
+    fn g1(&self, cos: f32) -> f32 {
+        let a2 = self.alpha * self.alpha;
+        2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+    }
+
+    /// Samples a reflected direction from two uniform numbers, by picking a microfacet normal
+    /// visible from `view`, following "Sampling the GGX Distribution of Visible Normals" by Eric
+    /// Heitz.
+    #[inline(always)]
+    fn sample(&self, normal: Vec3A, view: Vec3A, u: Vec2) -> Vec3A {
+        let (tangent, bitangent) = tangent_frame(normal);
+        let local = Vec3A::new(view.dot(tangent), view.dot(bitangent), view.dot(normal));
+
+        // Stretch the view so the distribution becomes a hemisphere
+        let v = Vec3A::new(self.alpha * local.x, self.alpha * local.y, local.z).normalize();
+        let length_sq = v.x * v.x + v.y * v.y;
+        let t1 = if length_sq > 0.0 {
+            Vec3A::new(-v.y, v.x, 0.0) / length_sq.sqrt()
+        } else {
+            Vec3A::X
+        };
+        let t2 = v.cross(t1);
+
+        // Uniform point on the projected hemisphere
+        let r = u.x.sqrt();
+        let phi = 2.0 * PI * u.y;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        // Unstretch back to a microfacet normal, then to world space
+        let h = Vec3A::new(self.alpha * n.x, self.alpha * n.y, n.z.max(0.0)).normalize();
+        let h = h.x * tangent + h.y * bitangent + h.z * normal;
+        2.0 * view.dot(h) * h - view
+    }
+
+    /// Probability density of [`Ggx::sample`] returning `light`.
+    #[inline(always)]
+    fn pdf(&self, normal: Vec3A, view: Vec3A, light: Vec3A) -> f32 {
+        let cos_v = view.dot(normal);
+        let Some(h) = (view + light).try_normalize() else {
+            return 0.0;
+        };
+        if cos_v <= 0.0 || light.dot(normal) <= 0.0 {
+            return 0.0;
+        }
+        self.g1(cos_v) * self.d(h.dot(normal)) / (4.0 * cos_v)
     }
 
+    /// The Cook-Torrance specular BRDF, for a reflectance of `f0` at normal incidence.
     #[inline(always)]
-    fn attenuation(&self) -> &Color {
-        &self.albedo
+    fn eval(&self, normal: Vec3A, view: Vec3A, light: Vec3A, f0: Vec3) -> Vec3 {
+        let cos_v = view.dot(normal);
+        let cos_l = light.dot(normal);
+        let Some(h) = (view + light).try_normalize() else {
+            return Vec3::ZERO;
+        };
+        if cos_v <= 0.0 || cos_l <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let g = self.g1(cos_v) * self.g1(cos_l);
+        schlick(f0, view.dot(h)) * self.d(h.dot(normal)) * g / (4.0 * cos_v * cos_l)
     }
 }
@@ -1,11 +1,55 @@
-use glam::Vec3A;
+use glam::{Vec3, Vec3A, Vec4};
 use std::sync::Arc;
 
-use crate::{Camera, Color, Material, Scene, Sdf};
+use crate::{color::luminance, light, sampler::Sampler, Camera, Color, Material, Scene, Sdf};
 
 const DIST_EPSILON: f32 = 0.0001;
 const RAY_OFFSET: f32 = DIST_EPSILON * 10.0;
 const MAX_DIST: f32 = 100000000.0;
+/// Number of bounces before Russian roulette starts terminating paths.
+const ROULETTE_START: u32 = 3;
+/// Paths always have a chance of being terminated, so rays trapped between perfect mirrors
+/// eventually stop.
+const MAX_SURVIVAL: f32 = 0.95;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use glam::{Vec3, Vec3A};
+
+    use crate::{
+        environment::Background,
+        material::Lambertian,
+        ray::Ray,
+        sdf::{Plane, Sphere},
+        Camera, Scene, SdfObject,
+    };
+
+    #[test]
+    fn surfaces_behind_a_light_dont_cast_shadows() {
+        let white = Arc::new(Lambertian::new(Vec3::ONE.extend(1.0).into()));
+        let mut scene = Scene {
+            camera: Camera::from_aspect_ratio(1.0),
+            // A ceiling just above a light hanging at y = 1, which the first step lands on
+            objects: vec![SdfObject::new(Plane::new(-Vec3::Y, -1.05), white.clone())],
+            materials: Default::default(),
+            lights: Vec::new(),
+            background: Background::default(),
+        };
+        let shadow_ray = Ray {
+            origin: Vec3A::ZERO,
+            direction: Vec3A::Y,
+        };
+        assert!(!shadow_ray.occluded(&scene, 1.0));
+        assert!(shadow_ray.occluded(&scene, 2.0));
+        // Objects between the surface and the light still do
+        scene
+            .objects
+            .push(SdfObject::new(Sphere::new(Vec3::Y * 0.5, 0.2), white));
+        assert!(shadow_ray.occluded(&scene, 1.0));
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Ray {
@@ -23,39 +67,123 @@ impl Ray {
         self.direction - 2.0 * self.direction.dot(normal) * normal
     }
 
-    #[inline(always)]
-    pub fn color(&self, scene: &Scene, max_bounces: u8) -> Color {
-        if max_bounces == 0 {
-            return [0.0, 0.0, 0.0, 1.0].into();
-        }
+    /// Traces a path starting with this ray, returning the radiance it carries back. Paths end
+    /// when they escape the scene, are absorbed, are terminated by Russian roulette, or reach
+    /// `max_bounces` rays when given. All random decisions along the path are drawn from `sampler`.
+    pub fn color(
+        &self,
+        scene: &Scene,
+        max_bounces: Option<u32>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut ray = self.clone();
+        let mut radiance = Vec3::ZERO;
+        // Fraction of the light arriving along `ray` that reaches the camera
+        let mut throughput = Vec3::ONE;
+        // Pdf of the material that scattered `ray`, or `None` for camera rays and specular
+        // bounces, which next event estimation couldn't have sampled
+        let mut bsdf_pdf = None;
+        let mut bounce = 0;
 
-        if let Some((hit, material)) = self.closest_hit(scene) {
-            let scatter_dir = material.scatter(&hit);
-            // Prevent NaN/inf errors by checking the direction can be normalized
-            let scatter_dir = scatter_dir.try_normalize().unwrap_or(hit.normal);
-            let mut scatter_ray = Ray {
-                origin: hit.position,
-                direction: scatter_dir,
+        while max_bounces.is_none_or(|max| bounce < max) {
+            let Some((hit, material)) = ray.closest_hit(scene) else {
+                let mut background = scene.background.radiance(ray.direction);
+                if let Some(bsdf_pdf) = bsdf_pdf {
+                    let light_pdf = light::pdf(scene, ray.origin, ray.direction);
+                    background *= power_heuristic(bsdf_pdf, light_pdf);
+                }
+                radiance += throughput * background;
+                break;
             };
-            // Move the ray away from the surface to prevent artifacts
-            scatter_ray.origin = scatter_ray.at(RAY_OFFSET);
-            material.attenuation() * scatter_ray.color(scene, max_bounces - 1)
-        } else {
-            let t = 0.5 * (self.direction.y + 1.0);
-            let color = (1.0 - t) + t * Vec3A::new(0.5, 0.7, 1.0);
-            [color.x, color.y, color.z, 1.0].into()
+
+            let mut emitted = material.emitted(&hit).rgb();
+            if let Some(bsdf_pdf) = bsdf_pdf {
+                // The light may also have been sampled directly from the previous hit
+                let light_pdf = light::pdf(scene, ray.origin, ray.direction);
+                emitted *= power_heuristic(bsdf_pdf, light_pdf);
+            }
+            radiance += throughput * emitted;
+
+            let Some(sample) = material.scatter(&hit, sampler) else {
+                break;
+            };
+            // Light sampled from here travels one more ray, which may be past the limit
+            let last_bounce = max_bounces.is_some_and(|max| bounce + 1 >= max);
+            if !sample.is_specular && !last_bounce {
+                let direct = direct_light(scene, &hit, material.as_ref(), sampler)
+                    + punctual_lights(scene, &hit, material.as_ref());
+                radiance += throughput * direct.rgb();
+            }
+
+            throughput *= sample.weight.rgb();
+            if bounce >= ROULETTE_START {
+                // Paths that carry little light are likely to be terminated, and the survivors
+                // are boosted to make up for the others, which keeps the estimate unbiased
+                let survival = luminance(throughput).min(MAX_SURVIVAL);
+                if sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+
+            // Move the ray away from the surface to prevent artifacts, on the side of the surface
+            // the scattered ray leaves towards, so transmitted rays start inside the object.
+            let side = if sample.direction.dot(hit.normal) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            ray = Ray {
+                origin: hit.position + hit.normal * side * RAY_OFFSET,
+                direction: sample.direction,
+            };
+            bsdf_pdf = (!sample.is_specular).then_some(sample.pdf);
+            bounce += 1;
         }
+
+        radiance.extend(1.0).into()
     }
 
+    /// Sphere traces the ray through the scene. Distances are taken unsigned, so rays that travel
+    /// inside an object (where its distance is negative) march towards the surface they exit from.
     #[inline(always)]
     fn closest_hit(&self, scene: &Scene) -> Option<(RayHit, Arc<dyn Material>)> {
+        let (index, ray_pos) = self.march(scene, f32::INFINITY)?;
+        let outward_normal = scene.objects[index].normal(ray_pos);
+        let front_face = self.direction.dot(outward_normal) < 0.0;
+        Some((
+            RayHit {
+                position: ray_pos,
+                normal: if front_face {
+                    outward_normal
+                } else {
+                    -outward_normal
+                },
+                front_face,
+                in_dir: self.to_owned(),
+            },
+            scene.objects[index].material.clone(),
+        ))
+    }
+
+    /// Returns `true` if an object is in the way of the ray within `max_distance`.
+    #[inline(always)]
+    fn occluded(&self, scene: &Scene, max_distance: f32) -> bool {
+        self.march(scene, max_distance).is_some()
+    }
+
+    /// Marches the ray until it hits a surface, returning the index of the object and the hit
+    /// position, or `None` if it escapes the scene or travels further than `max_distance`.
+    #[inline(always)]
+    fn march(&self, scene: &Scene, max_distance: f32) -> Option<(usize, Vec3A)> {
         let mut ray_pos = self.origin;
+        let mut travelled = 0.0;
         for _ in 0..10_000_000 {
             let (index, distance) = scene
                 .objects
                 .iter()
                 .enumerate()
-                .map(|(i, obj)| (i, obj.distance(ray_pos)))
+                .map(|(i, obj)| (i, obj.distance(ray_pos).abs()))
                 .reduce(
                     |(i, accum), (j, item)| {
                         if item < accum {
@@ -64,22 +192,16 @@ impl Ray {
                             (i, accum)
                         }
                     },
-                )
-                .unwrap();
-
-            if distance <= DIST_EPSILON {
-                return Some((
-                    RayHit {
-                        position: ray_pos,
-                        normal: scene.objects[index].normal(ray_pos),
-                        in_dir: self.to_owned(),
-                    },
-                    scene.objects[index].material.clone(),
-                ));
-            } else if ray_pos.length_squared() > MAX_DIST {
+                )?;
+
+            // A step can overshoot `max_distance` and land on a surface beyond it, which doesn't count
+            if travelled >= max_distance || ray_pos.length_squared() > MAX_DIST {
                 break;
+            } else if distance <= DIST_EPSILON {
+                return Some((index, ray_pos));
             }
             ray_pos += self.direction * distance;
+            travelled += distance;
         }
         None
     }
@@ -89,26 +211,93 @@ impl Ray {
         let base_ray = camera
             .inv_projection
             .transform_point3a(Vec3A::new(u, v, 0.0));
-        let direction = camera.transform.transform_point3a(base_ray).normalize();
+        let direction = camera
+            .inv_transform
+            .transform_vector3a(base_ray)
+            .normalize();
         let origin = camera.inv_transform.transform_point3a(Vec3A::ZERO);
         Ray { origin, direction }
     }
 }
 
-pub struct RayHit {
-    pub in_dir: Ray,
-    pub position: Vec3A,
-    pub normal: Vec3A,
+/// Next event estimation: light arriving at `hit` straight from a sampled light source, weighted
+/// against finding the same light by scattering off the material.
+fn direct_light(
+    scene: &Scene,
+    hit: &RayHit,
+    material: &dyn Material,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let black = Color::from(Vec4::ZERO);
+    let Some(light) = light::sample(scene, hit.position, sampler) else {
+        return black;
+    };
+    let (value, bsdf_pdf) = material.eval(hit, light.direction);
+    if value.rgb().max_element() <= 0.0 {
+        return black;
+    }
+    let side = if light.direction.dot(hit.normal) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let shadow_ray = Ray {
+        origin: hit.position + hit.normal * side * RAY_OFFSET,
+        direction: light.direction,
+    };
+    // Whatever the shadow ray hits first is lit, so occluders simply don't emit anything
+    let radiance = match shadow_ray.closest_hit(scene) {
+        Some((light_hit, light_material)) => light_material.emitted(&light_hit),
+        None => Color::from(scene.background.radiance(light.direction).extend(0.0)),
+    };
+    radiance * value * (power_heuristic(light.pdf, bsdf_pdf) / light.pdf)
 }
 
-/// Returns a random point from the surface of a sphere.
-#[inline(always)]
-pub fn rand_on_unit_sphere() -> Vec3A {
-    loop {
-        let p = Vec3A::new(fastrand::f32(), fastrand::f32(), fastrand::f32()) * 2.0 - 1.0;
-        if p.length_squared() >= 1.0 {
+/// Light arriving at `hit` from the [`crate::light::Light`]s of the scene.
+fn punctual_lights(scene: &Scene, hit: &RayHit, material: &dyn Material) -> Color {
+    let mut total = Color::from(Vec4::ZERO);
+    for light in &scene.lights {
+        let Some(incident) = light.illuminate(hit.position) else {
             continue;
         };
-        return p.normalize();
+        let (value, _) = material.eval(hit, incident.direction);
+        if value.rgb().max_element() <= 0.0 {
+            continue;
+        }
+        let side = if incident.direction.dot(hit.normal) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let shadow_ray = Ray {
+            origin: hit.position + hit.normal * side * RAY_OFFSET,
+            direction: incident.direction,
+        };
+        if !shadow_ray.occluded(scene, incident.distance) {
+            total += value * Color::from(incident.irradiance.extend(0.0));
+        }
+    }
+    total
+}
+
+/// Veach's power heuristic, the weight of a sample taken with density `pdf` against another
+/// strategy that could have sampled it with density `other_pdf`.
+#[inline(always)]
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
     }
 }
+
+pub struct RayHit {
+    pub in_dir: Ray,
+    pub position: Vec3A,
+    /// Surface normal, facing against the incoming ray.
+    pub normal: Vec3A,
+    /// `true` if the ray hit the outside of the surface, `false` if it hit it from inside the
+    /// object.
+    pub front_face: bool,
+}
@@ -0,0 +1,520 @@
+//! Sample sequences, providing the numbers in `[0, 1)` every random decision of a path is made
+//! from.
+//!
+//! Each sample of a pixel draws its numbers as a series of dimensions: the first two place the
+//! sample in the pixel, the next ones pick the directions and lights of the first bounce, and so
+//! on. Independent random numbers clump together and leave gaps, while low-discrepancy sequences
+//! spread the samples of a pixel evenly over every dimension, which lowers noise at the same
+//! sample count.
+
+use std::sync::OnceLock;
+
+use dyn_clone::{clone_trait_object, DynClone};
+use fastrand::Rng;
+use glam::Vec2;
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use crate::sampler::{all, blue_noise, permute, Independent, Sampler, BLUE_NOISE_SIZE};
+
+    /// Mean error of estimating the area of a quarter disk with `samples` samples per pixel, over
+    /// a few pixels.
+    fn quarter_disk_error(sampler: &mut dyn Sampler, samples: u32) -> f32 {
+        let mut error = 0.0;
+        for pixel in 0..16 {
+            let mut inside = 0;
+            for index in 0..samples {
+                sampler.start_sample(7, [pixel, 3], index, samples);
+                // Skip the pixel dimensions, like a path would
+                sampler.next_2d();
+                let p = sampler.next_2d();
+                assert!(p.cmpge(glam::Vec2::ZERO).all() && p.cmplt(glam::Vec2::ONE).all());
+                if p.length_squared() < 1.0 {
+                    inside += 1;
+                }
+            }
+            error += (inside as f32 / samples as f32 - PI / 4.0).abs();
+        }
+        error / 16.0
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_beat_random() {
+        let random = quarter_disk_error(&mut Independent::default(), 64);
+        for mut sampler in all().into_iter().skip(1) {
+            let error = quarter_disk_error(sampler.as_mut(), 64);
+            assert!(error < random, "{}: {error} >= {random}", sampler.name());
+        }
+    }
+
+    #[test]
+    fn stratum_permutations_take_any_seed() {
+        for seed in [0, 0x8000_0000, u32::MAX - 3, u32::MAX] {
+            let mut strata: Vec<u32> = (0..13).map(|index| permute(index, 13, seed)).collect();
+            strata.sort_unstable();
+            assert!(strata.into_iter().eq(0..13));
+        }
+    }
+
+    #[test]
+    fn blue_noise_is_a_permutation() {
+        let mut values = blue_noise().to_vec();
+        values.sort_by(f32::total_cmp);
+        let n = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        for (rank, value) in values.into_iter().enumerate() {
+            assert_eq!(value, (rank as f32 + 0.5) / n as f32);
+        }
+    }
+}
+
+/// Generates the sample values of each pixel of a render.
+pub trait Sampler: Send + Sync + DynClone {
+    /// Starts sample `index` of the `samples` traced through the pixel at `position`. Values
+    /// drawn until the next call belong to that sample, and only depend on the arguments.
+    fn start_sample(&mut self, seed: u64, position: [u32; 2], index: u32, samples: u32);
+    /// Next dimension of the current sample.
+    fn next_1d(&mut self) -> f32;
+    /// Next two dimensions of the current sample, which are well distributed as a pair.
+    fn next_2d(&mut self) -> Vec2;
+    /// Name shown in user interfaces, and accepted by [`by_name`].
+    fn name(&self) -> &'static str;
+}
+
+clone_trait_object!(Sampler);
+
+/// Every built-in sampler.
+pub fn all() -> Vec<Box<dyn Sampler>> {
+    vec![
+        Box::new(Independent::default()),
+        Box::new(Stratified::default()),
+        Box::new(Halton::default()),
+        Box::new(Sobol::default()),
+        Box::new(BlueNoise::default()),
+    ]
+}
+
+/// Finds a built-in sampler by name, ignoring case.
+pub fn by_name(name: &str) -> Option<Box<dyn Sampler>> {
+    all()
+        .into_iter()
+        .find(|sampler| sampler.name().eq_ignore_ascii_case(name))
+}
+
+/// Largest `f32` below 1.0.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Uniform random numbers, from a stream that only depends on the seed, pixel and sample index.
+#[derive(Clone, Default)]
+pub struct Independent {
+    state: u64,
+}
+impl Sampler for Independent {
+    fn start_sample(&mut self, seed: u64, position: [u32; 2], index: u32, _samples: u32) {
+        self.state = hash(&[seed, pixel(position), index as u64]);
+    }
+
+    #[inline(always)]
+    fn next_1d(&mut self) -> f32 {
+        // SplitMix64, hashing a counter
+        self.state = self.state.wrapping_add(1);
+        to_unit((mix(self.state) >> 32) as u32)
+    }
+
+    #[inline(always)]
+    fn next_2d(&mut self) -> Vec2 {
+        Vec2::new(self.next_1d(), self.next_1d())
+    }
+
+    fn name(&self) -> &'static str {
+        "Independent"
+    }
+}
+
+/// Jittered strata: every dimension is split into as many strata as there are samples per pixel,
+/// and each sample lands in a different one. Pairs of dimensions use a 2D grid of strata. The
+/// strata of each dimension are shuffled separately, so dimensions aren't correlated.
+#[derive(Clone, Default)]
+pub struct Stratified {
+    jitter: Independent,
+    pixel: u64,
+    index: u32,
+    samples: u32,
+    dimension: u32,
+}
+impl Sampler for Stratified {
+    fn start_sample(&mut self, seed: u64, position: [u32; 2], index: u32, samples: u32) {
+        self.jitter.start_sample(seed, position, index, samples);
+        self.pixel = hash(&[seed, pixel(position)]);
+        self.index = index;
+        self.samples = samples.max(1);
+        self.dimension = 0;
+    }
+
+    #[inline(always)]
+    fn next_1d(&mut self) -> f32 {
+        let stratum = self.stratum(self.samples);
+        ((stratum as f32 + self.jitter.next_1d()) / self.samples as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    #[inline(always)]
+    fn next_2d(&mut self) -> Vec2 {
+        // A grid with at least one stratum per sample, as square as possible
+        let columns = (self.samples as f32).sqrt().ceil() as u32;
+        let rows = self.samples.div_ceil(columns);
+        let stratum = self.stratum(columns * rows);
+        let cell = Vec2::new((stratum % columns) as f32, (stratum / columns) as f32);
+        ((cell + self.jitter.next_2d()) / Vec2::new(columns as f32, rows as f32))
+            .min(Vec2::splat(ONE_MINUS_EPSILON))
+    }
+
+    fn name(&self) -> &'static str {
+        "Stratified"
+    }
+}
+impl Stratified {
+    /// The stratum of the current sample in the next dimension, out of `count`.
+    #[inline(always)]
+    fn stratum(&mut self, count: u32) -> u32 {
+        let seed = hash(&[self.pixel, self.dimension as u64]) as u32;
+        self.dimension += 1;
+        permute(self.index % count, count, seed)
+    }
+}
+
+/// The first bases of the Halton sequence. Dimensions past these are filled with random numbers.
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, which uses the radical inverse of the sample index in a different prime
+/// base for each dimension. Each pixel shifts the sequence by a random offset per dimension
+/// (a Cranley-Patterson rotation), so neighbouring pixels don't repeat the same pattern.
+#[derive(Clone, Default)]
+pub struct Halton {
+    fallback: Independent,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+}
+impl Sampler for Halton {
+    fn start_sample(&mut self, seed: u64, position: [u32; 2], index: u32, samples: u32) {
+        self.fallback.start_sample(seed, position, index, samples);
+        self.pixel = hash(&[seed, pixel(position)]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    #[inline(always)]
+    fn next_1d(&mut self) -> f32 {
+        let Some(&base) = PRIMES.get(self.dimension) else {
+            return self.fallback.next_1d();
+        };
+        let offset = to_unit(hash(&[self.pixel, self.dimension as u64]) as u32);
+        self.dimension += 1;
+        (radical_inverse(base, self.index) + offset)
+            .fract()
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    #[inline(always)]
+    fn next_2d(&mut self) -> Vec2 {
+        Vec2::new(self.next_1d(), self.next_1d())
+    }
+
+    fn name(&self) -> &'static str {
+        "Halton"
+    }
+}
+
+/// The Sobol sequence with Owen scrambling, following "Practical Hash-based Owen Scrambling" by
+/// Brent Burley. Every pair of dimensions uses the first two Sobol dimensions, with the order of
+/// the samples shuffled differently for each pair, so the sequence never runs out of dimensions.
+#[derive(Clone, Default)]
+pub struct Sobol {
+    seed: u64,
+    index: u32,
+    dimension: u32,
+}
+impl Sampler for Sobol {
+    fn start_sample(&mut self, seed: u64, position: [u32; 2], index: u32, _samples: u32) {
+        self.seed = hash(&[seed, pixel(position)]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    #[inline(always)]
+    fn next_1d(&mut self) -> f32 {
+        self.next_2d().x
+    }
+
+    #[inline(always)]
+    fn next_2d(&mut self) -> Vec2 {
+        let point = owen_sobol(self.seed, self.dimension, self.index);
+        self.dimension += 1;
+        point
+    }
+
+    fn name(&self) -> &'static str {
+        "Sobol"
+    }
+}
+
+/// Owen-scrambled Sobol samples shared by every pixel, each pixel offsetting them by the value of
+/// a blue noise mask. The error of neighbouring pixels is then uncorrelated at short distances,
+/// which looks like fine grain instead of blotches at low sample counts.
+#[derive(Clone, Default)]
+pub struct BlueNoise {
+    seed: u64,
+    position: [u32; 2],
+    index: u32,
+    dimension: u32,
+}
+impl Sampler for BlueNoise {
+    fn start_sample(&mut self, seed: u64, position: [u32; 2], index: u32, _samples: u32) {
+        self.seed = hash(&[seed]);
+        self.position = position;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    #[inline(always)]
+    fn next_1d(&mut self) -> f32 {
+        self.next_2d().x
+    }
+
+    #[inline(always)]
+    fn next_2d(&mut self) -> Vec2 {
+        let point = owen_sobol(self.seed, self.dimension, self.index);
+        // Each dimension reads the mask at a different offset, so they aren't correlated
+        let shift = hash(&[self.seed, self.dimension as u64]);
+        let offset = Vec2::new(
+            self.mask(shift as u32 as usize),
+            self.mask((shift >> 32) as usize),
+        );
+        self.dimension += 1;
+        (point + offset).fract().min(Vec2::splat(ONE_MINUS_EPSILON))
+    }
+
+    fn name(&self) -> &'static str {
+        "Blue noise"
+    }
+}
+impl BlueNoise {
+    /// The blue noise mask tiled over the image, at the current pixel moved by `shift`.
+    #[inline(always)]
+    fn mask(&self, shift: usize) -> f32 {
+        let x = (self.position[0] as usize + shift) % BLUE_NOISE_SIZE;
+        let y = (self.position[1] as usize + (shift >> 8)) % BLUE_NOISE_SIZE;
+        blue_noise()[y * BLUE_NOISE_SIZE + x]
+    }
+}
+
+/// Width and height of the blue noise mask.
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// A tileable blue noise mask, holding every value `(rank + 0.5) / n` once, generated with the
+/// void-and-cluster method by Robert Ulichney on first use.
+fn blue_noise() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        const N: usize = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        const RADIUS: isize = 6;
+        const SIGMA: f32 = 1.5;
+
+        // Energy of each pixel: the sum of a gaussian centered on each set pixel
+        let mut energy = vec![0.0f32; N];
+        let update = |energy: &mut [f32], index: usize, sign: f32| {
+            let (x, y) = (
+                (index % BLUE_NOISE_SIZE) as isize,
+                (index / BLUE_NOISE_SIZE) as isize,
+            );
+            for dy in -RADIUS..=RADIUS {
+                for dx in -RADIUS..=RADIUS {
+                    let weight = (-((dx * dx + dy * dy) as f32) / (2.0 * SIGMA * SIGMA)).exp();
+                    let size = BLUE_NOISE_SIZE as isize;
+                    let i = (y + dy).rem_euclid(size) * size + (x + dx).rem_euclid(size);
+                    energy[i as usize] += sign * weight;
+                }
+            }
+        };
+        // The tightest cluster is the set pixel with the most energy, the largest void the unset
+        // pixel with the least
+        let extreme = |energy: &[f32], set: &[bool], value: bool| {
+            let pixels = (0..N).filter(|&i| set[i] == value);
+            if value {
+                pixels.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            } else {
+                pixels.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            }
+            .expect("the mask has pixels of both kinds")
+        };
+
+        // Start from random pixels, and move them from clusters to voids until they are even
+        let rng = Rng::with_seed(0);
+        let mut set = vec![false; N];
+        let initial = N / 10;
+        while set.iter().filter(|&&set| set).count() < initial {
+            let i = rng.usize(..N);
+            if !set[i] {
+                set[i] = true;
+                update(&mut energy, i, 1.0);
+            }
+        }
+        loop {
+            let cluster = extreme(&energy, &set, true);
+            set[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+            let void = extreme(&energy, &set, false);
+            set[void] = true;
+            update(&mut energy, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        // Rank the initial pixels by removing clusters, then fill voids for the other ranks
+        let mut rank = vec![0; N];
+        let (initial_set, initial_energy) = (set.clone(), energy.clone());
+        for r in (0..initial).rev() {
+            let cluster = extreme(&energy, &set, true);
+            set[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+            rank[cluster] = r;
+        }
+        let (mut set, mut energy) = (initial_set, initial_energy);
+        for r in initial..N {
+            let void = extreme(&energy, &set, false);
+            set[void] = true;
+            update(&mut energy, void, 1.0);
+            rank[void] = r;
+        }
+        rank.into_iter()
+            .map(|rank| (rank as f32 + 0.5) / N as f32)
+            .collect()
+    })
+}
+
+/// A point of the Owen-scrambled Sobol sequence, in the pair of dimensions `dimension`.
+#[inline(always)]
+fn owen_sobol(seed: u64, dimension: u32, index: u32) -> Vec2 {
+    let seed = hash(&[seed, dimension as u64]);
+    let index = owen_scramble(index, seed as u32);
+    let [x, y] = sobol(index);
+    Vec2::new(
+        to_unit(owen_scramble(x, (seed >> 32) as u32)),
+        to_unit(owen_scramble(y, (seed >> 32) as u32 ^ 0x5bd1_e995)),
+    )
+}
+
+/// The first two dimensions of the Sobol sequence, as 32-bit fractions.
+#[inline(always)]
+fn sobol(index: u32) -> [u32; 2] {
+    // The first dimension is the van der Corput sequence, the second has direction numbers
+    // following Pascal's triangle modulo 2
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    let mut bits = index;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            y ^= direction;
+        }
+        bits >>= 1;
+        direction ^= direction >> 1;
+    }
+    [index.reverse_bits(), y]
+}
+
+/// Nested uniform scrambling of a 32-bit fraction: flips each bit depending on the bits above it.
+#[inline(always)]
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    // The Laine-Karras permutation scrambles from the lowest bit up, so it's applied reversed
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// Radical inverse of `index` in `base`: its digits mirrored around the decimal point.
+#[inline(always)]
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let (mut reversed, mut scale) = (0u64, 1.0f64);
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base as u64 + (index - next * base) as u64;
+        scale *= inverse_base;
+        index = next;
+    }
+    (reversed as f64 * scale) as f32
+}
+
+/// Element `index` of a random permutation of `0..count` picked by `seed`, from "Correlated
+/// Multi-Jittered Sampling" by Andrew Kensler.
+#[inline(always)]
+fn permute(mut index: u32, count: u32, seed: u32) -> u32 {
+    let mut mask = count - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // Permutes the next power of two, until landing back in range
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < count {
+            break;
+        }
+    }
+    // Summed in 64 bits, since wrapping around would break the permutation
+    ((index as u64 + seed as u64) % count as u64) as u32
+}
+
+/// A uniform number in `[0, 1)` from the upper bits of `x`.
+#[inline(always)]
+fn to_unit(x: u32) -> f32 {
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Packs a pixel position in a single value.
+#[inline(always)]
+fn pixel(position: [u32; 2]) -> u64 {
+    (position[1] as u64) << 32 | position[0] as u64
+}
+
+/// Hashes `values` together, with every bit of each of them affecting the whole output.
+#[inline(always)]
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |hash, &value| mix(hash ^ value))
+}
+
+/// The SplitMix64 finalizer.
+#[inline(always)]
+fn mix(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,783 @@
+//! A text scene format, written in [RON](https://github.com/ron-rs/ron).
+//!
+//! A scene file describes the camera, a set of named materials, the objects in the scene, each
+//! referencing one of the materials by name, and optionally a list of punctual lights:
+//!
+//! ```ron
+//! (
+//!     camera: (position: (0.0, 0.0, 0.0), target: (0.0, 0.0, -1.0), vertical_fov: 90.0),
+//!     materials: {
+//!         "red": Lambertian(albedo: (0.99, 0.1, 0.1)),
+//!     },
+//!     objects: [
+//!         (shape: Sphere(center: (0.0, 0.0, -1.0), radius: 0.5), material: "red"),
+//!     ],
+//!     lights: [
+//!         Point(position: (0.0, 2.0, 0.0), intensity: 10.0),
+//!     ],
+//! )
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Affine3A, EulerRot, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    csg::{Intersection, Subtraction, Union, Xor},
+    environment::{Background, Environment, EnvironmentMap, Gradient, Sky},
+    image::{self, ImageError},
+    light::Light,
+    material::{Dielectric, Emissive, Lambertian, Material, Metal, Principled},
+    sdf::{
+        BoxFrame, CappedTorus, Capsule, Cone, Cuboid, Cylinder, Ellipsoid, HexagonalPrism,
+        Octahedron, Plane, RoundedBox, Sphere, Torus,
+    },
+    transform::Transformed,
+    Camera, Scene, Sdf, SdfObject,
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::{scene::SceneError, Scene};
+
+    #[test]
+    fn round_trip() {
+        let scene = Scene::demo(1.0);
+        let text = scene.to_ron().unwrap();
+        let reloaded = Scene::from_ron(&text).unwrap();
+        assert_eq!(reloaded.objects.len(), scene.objects.len());
+        assert_eq!(reloaded.materials.len(), scene.materials.len());
+        assert_eq!(reloaded.to_ron().unwrap(), text);
+    }
+
+    #[test]
+    fn parse_error_has_line() {
+        let text = "(\n    camera: (),\n    materials: {},\n    objects: [ oops ],\n)";
+        match Scene::from_ron(text) {
+            Err(SceneError::Parse { line, .. }) => assert_eq!(line, 4),
+            other => panic!("expected a parse error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn unknown_material_has_line() {
+        let text = "(\n    objects: [\n        (shape: Sphere(center: (0.0, 0.0, 0.0), radius: 1.0), material: \"gold\"),\n    ],\n)";
+        match Scene::from_ron(text) {
+            Err(SceneError::UnknownMaterial { name, line }) => {
+                assert_eq!(name, "gold");
+                assert_eq!(line, Some(3));
+            }
+            other => panic!("expected an unknown material error, got {:?}", other.err()),
+        }
+    }
+}
+
+/// Serialized form of a [`Scene`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneDescriptor {
+    pub camera: CameraDescriptor,
+    pub materials: BTreeMap<String, MaterialDescriptor>,
+    pub objects: Vec<ObjectDescriptor>,
+    pub lights: Vec<LightDescriptor>,
+    pub background: BackgroundDescriptor,
+}
+
+/// Serialized form of a [`Background`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundDescriptor {
+    pub environment: EnvironmentDescriptor,
+    /// Rotation around the vertical axis, in degrees.
+    pub rotation: f32,
+    pub intensity: f32,
+}
+impl Default for BackgroundDescriptor {
+    fn default() -> Self {
+        Self {
+            environment: EnvironmentDescriptor::Sky {
+                elevation: 45.0,
+                azimuth: 0.0,
+                turbidity: 3.0,
+            },
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EnvironmentDescriptor {
+    /// A physically based daytime sky and sun, with angles in degrees.
+    Sky {
+        /// Height of the sun above the horizon.
+        elevation: f32,
+        /// Direction of the sun, clockwise from -Z when seen from above.
+        #[serde(default)]
+        azimuth: f32,
+        /// From 2.0 for a very clear sky to 10.0 for a hazy one.
+        #[serde(default = "three")]
+        turbidity: f32,
+    },
+    Gradient,
+    /// An equirectangular `.hdr` or `.pfm` image. Relative paths are resolved from the directory
+    /// of the scene file.
+    Map {
+        path: PathBuf,
+    },
+}
+impl EnvironmentDescriptor {
+    /// Builds the environment, loading images relative to `directory`.
+    pub fn build(&self, directory: &Path) -> Result<Arc<dyn Environment>, SceneError> {
+        match self {
+            EnvironmentDescriptor::Sky {
+                elevation,
+                azimuth,
+                turbidity,
+            } => Ok(Arc::new(Sky::new(
+                elevation.to_radians(),
+                azimuth.to_radians(),
+                *turbidity,
+            ))),
+            EnvironmentDescriptor::Gradient => Ok(Arc::new(Gradient)),
+            EnvironmentDescriptor::Map { path } => {
+                let map = image::load_hdr(directory.join(path))
+                    .and_then(|image| EnvironmentMap::from_image(image, path.clone()))
+                    .map_err(|error| SceneError::Environment {
+                        path: path.clone(),
+                        error,
+                    })?;
+                Ok(Arc::new(map))
+            }
+        }
+    }
+}
+
+/// Serialized form of a [`Camera`]. The aspect ratio is not stored, as it depends on the
+/// resolution the scene is rendered at.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraDescriptor {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view, in degrees.
+    pub vertical_fov: f32,
+}
+impl Default for CameraDescriptor {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            target: -Vec3::Z,
+            up: Vec3::Y,
+            vertical_fov: 90.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectDescriptor {
+    pub shape: ShapeDescriptor,
+    /// Name of an entry in [`SceneDescriptor::materials`].
+    pub material: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ShapeDescriptor {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// An infinite plane facing `normal`, at `offset` from the origin along it.
+    Plane {
+        normal: Vec3,
+        #[serde(default)]
+        offset: f32,
+    },
+    /// An axis-aligned box, extending `half_extents` from its center along each axis.
+    Box {
+        center: Vec3,
+        half_extents: Vec3,
+    },
+    RoundedBox {
+        center: Vec3,
+        half_extents: Vec3,
+        radius: f32,
+    },
+    /// The edges of a box, as beams `2 * thickness` wide.
+    BoxFrame {
+        center: Vec3,
+        half_extents: Vec3,
+        thickness: f32,
+    },
+    /// A ring around the Y axis.
+    Torus {
+        center: Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// An arc of a torus in the XY plane, spanning `angle` degrees to each side of +Y.
+    CappedTorus {
+        center: Vec3,
+        angle: f32,
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    Capsule {
+        start: Vec3,
+        end: Vec3,
+        radius: f32,
+    },
+    /// A cylinder along the Y axis.
+    Cylinder {
+        center: Vec3,
+        radius: f32,
+        half_height: f32,
+    },
+    /// A cone opening downwards from its tip, with `angle` degrees between its axis and side.
+    Cone {
+        tip: Vec3,
+        angle: f32,
+        height: f32,
+    },
+    Ellipsoid {
+        center: Vec3,
+        radii: Vec3,
+    },
+    /// A hexagonal prism along the Y axis, `radius` being the distance to its flat sides.
+    HexagonalPrism {
+        center: Vec3,
+        radius: f32,
+        half_height: f32,
+    },
+    Octahedron {
+        center: Vec3,
+        size: f32,
+    },
+    /// Everything inside either shape, joined by a fillet of `blend` radius when it's not zero.
+    Union {
+        a: Box<ShapeDescriptor>,
+        b: Box<ShapeDescriptor>,
+        #[serde(default)]
+        blend: f32,
+    },
+    /// Everything inside both shapes.
+    Intersection {
+        a: Box<ShapeDescriptor>,
+        b: Box<ShapeDescriptor>,
+        #[serde(default)]
+        blend: f32,
+    },
+    /// Everything inside `a` but outside `b`.
+    Subtraction {
+        a: Box<ShapeDescriptor>,
+        b: Box<ShapeDescriptor>,
+        #[serde(default)]
+        blend: f32,
+    },
+    /// Everything inside exactly one of the shapes.
+    Xor {
+        a: Box<ShapeDescriptor>,
+        b: Box<ShapeDescriptor>,
+        #[serde(default)]
+        blend: f32,
+    },
+    /// A shape scaled, then rotated by XYZ Euler angles in degrees, then translated.
+    Transformed {
+        shape: Box<ShapeDescriptor>,
+        #[serde(default)]
+        translation: Vec3,
+        #[serde(default)]
+        rotation: Vec3,
+        #[serde(default = "unscaled")]
+        scale: Vec3,
+    },
+}
+impl ShapeDescriptor {
+    pub fn build(&self) -> Box<dyn Sdf> {
+        match *self {
+            ShapeDescriptor::Sphere { center, radius } => Box::new(Sphere::new(center, radius)),
+            ShapeDescriptor::Plane { normal, offset } => Box::new(Plane::new(normal, offset)),
+            ShapeDescriptor::Box {
+                center,
+                half_extents,
+            } => Box::new(Cuboid::new(center, half_extents)),
+            ShapeDescriptor::RoundedBox {
+                center,
+                half_extents,
+                radius,
+            } => Box::new(RoundedBox::new(center, half_extents, radius)),
+            ShapeDescriptor::BoxFrame {
+                center,
+                half_extents,
+                thickness,
+            } => Box::new(BoxFrame::new(center, half_extents, thickness)),
+            ShapeDescriptor::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => Box::new(Torus::new(center, major_radius, minor_radius)),
+            ShapeDescriptor::CappedTorus {
+                center,
+                angle,
+                major_radius,
+                minor_radius,
+            } => Box::new(CappedTorus::new(
+                center,
+                angle.to_radians(),
+                major_radius,
+                minor_radius,
+            )),
+            ShapeDescriptor::Capsule { start, end, radius } => {
+                Box::new(Capsule::new(start, end, radius))
+            }
+            ShapeDescriptor::Cylinder {
+                center,
+                radius,
+                half_height,
+            } => Box::new(Cylinder::new(center, radius, half_height)),
+            ShapeDescriptor::Cone { tip, angle, height } => {
+                Box::new(Cone::new(tip, angle.to_radians(), height))
+            }
+            ShapeDescriptor::Ellipsoid { center, radii } => Box::new(Ellipsoid::new(center, radii)),
+            ShapeDescriptor::HexagonalPrism {
+                center,
+                radius,
+                half_height,
+            } => Box::new(HexagonalPrism::new(center, radius, half_height)),
+            ShapeDescriptor::Octahedron { center, size } => Box::new(Octahedron::new(center, size)),
+            ShapeDescriptor::Union {
+                ref a,
+                ref b,
+                blend,
+            } => Box::new(Union::smooth(a.build(), b.build(), blend)),
+            ShapeDescriptor::Intersection {
+                ref a,
+                ref b,
+                blend,
+            } => Box::new(Intersection::smooth(a.build(), b.build(), blend)),
+            ShapeDescriptor::Subtraction {
+                ref a,
+                ref b,
+                blend,
+            } => Box::new(Subtraction::smooth(a.build(), b.build(), blend)),
+            ShapeDescriptor::Xor {
+                ref a,
+                ref b,
+                blend,
+            } => Box::new(Xor::smooth(a.build(), b.build(), blend)),
+            ShapeDescriptor::Transformed {
+                ref shape,
+                translation,
+                rotation,
+                scale,
+            } => {
+                let [x, y, z] = rotation.to_array().map(f32::to_radians);
+                let rotation = Quat::from_euler(EulerRot::XYZ, x, y, z);
+                let transform =
+                    Affine3A::from_scale_rotation_translation(scale, rotation, translation);
+                Box::new(Transformed::new(shape.build(), transform))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MaterialDescriptor {
+    Lambertian {
+        albedo: Vec3,
+    },
+    Metal {
+        albedo: Vec3,
+        /// From 0.0 for a perfect mirror, to 1.0 for a very blurry reflection.
+        #[serde(default)]
+        roughness: f32,
+    },
+    Dielectric {
+        /// Index of refraction, e.g. 1.5 for glass or 1.33 for water.
+        ior: f32,
+        #[serde(default = "white")]
+        tint: Vec3,
+    },
+    Principled {
+        base_color: Vec3,
+        #[serde(default)]
+        metallic: f32,
+        #[serde(default = "half")]
+        roughness: f32,
+        /// Specular reflectance of non-metals, 0.5 being the 4% typical of most materials.
+        #[serde(default = "half")]
+        specular: f32,
+        #[serde(default)]
+        clearcoat: f32,
+    },
+    Emissive {
+        color: Vec3,
+        /// Multiplier applied to `color`, so lights can be brighter than 1.0.
+        #[serde(default = "one")]
+        intensity: f32,
+    },
+}
+impl MaterialDescriptor {
+    pub fn build(&self) -> Arc<dyn Material> {
+        match *self {
+            MaterialDescriptor::Lambertian { albedo } => {
+                Arc::new(Lambertian::new(albedo.extend(1.0).into()))
+            }
+            MaterialDescriptor::Metal { albedo, roughness } => {
+                Arc::new(Metal::new(albedo.extend(1.0).into(), roughness))
+            }
+            MaterialDescriptor::Dielectric { ior, tint } => {
+                Arc::new(Dielectric::new(ior, tint.extend(1.0).into()))
+            }
+            MaterialDescriptor::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+            } => Arc::new(Principled::new(
+                base_color.extend(1.0).into(),
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+            )),
+            MaterialDescriptor::Emissive { color, intensity } => {
+                Arc::new(Emissive::new(color.extend(1.0).into(), intensity))
+            }
+        }
+    }
+}
+
+/// Serialized form of a [`Light`], with angles in degrees.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum LightDescriptor {
+    Point {
+        position: Vec3,
+        #[serde(default = "white")]
+        color: Vec3,
+        /// Radiant intensity, in W/sr.
+        intensity: f32,
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        #[serde(default = "white")]
+        color: Vec3,
+        /// Radiant intensity, in W/sr.
+        intensity: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    Directional {
+        direction: Vec3,
+        #[serde(default = "white")]
+        color: Vec3,
+        /// Irradiance, in W/m².
+        irradiance: f32,
+    },
+}
+impl LightDescriptor {
+    pub fn build(&self) -> Light {
+        match *self {
+            LightDescriptor::Point {
+                position,
+                color,
+                intensity,
+            } => Light::Point {
+                position: position.into(),
+                color,
+                intensity,
+            },
+            LightDescriptor::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => Light::Spot {
+                position: position.into(),
+                direction: direction.normalize().into(),
+                color,
+                intensity,
+                inner_angle: inner_angle.to_radians(),
+                outer_angle: outer_angle.to_radians(),
+            },
+            LightDescriptor::Directional {
+                direction,
+                color,
+                irradiance,
+            } => Light::Directional {
+                direction: direction.normalize().into(),
+                color,
+                irradiance,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    /// The file is not valid RON, or doesn't match the scene format.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// An object references a material that isn't defined. The line is that of the first place
+    /// the name appears in the file, when it can be found.
+    UnknownMaterial {
+        name: String,
+        line: Option<usize>,
+    },
+    /// The scene contains a shape or material that has no scene file representation.
+    Unsupported(String),
+    Serialize(ron::Error),
+    /// The image of an environment map couldn't be loaded.
+    Environment {
+        path: PathBuf,
+        error: ImageError,
+    },
+}
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{e}"),
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {line}, column {column}: {message}"),
+            SceneError::UnknownMaterial {
+                name,
+                line: Some(line),
+            } => write!(f, "line {line}: unknown material \"{name}\""),
+            SceneError::UnknownMaterial { name, line: None } => {
+                write!(f, "unknown material \"{name}\"")
+            }
+            SceneError::Unsupported(what) => write!(f, "can't be saved: {what}"),
+            SceneError::Serialize(e) => write!(f, "{e}"),
+            SceneError::Environment { path, error } => {
+                write!(f, "could not load {}: {error}", path.display())
+            }
+        }
+    }
+}
+impl std::error::Error for SceneError {}
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl Scene {
+    /// Loads a scene file. The camera is given an aspect ratio of 1.0, use
+    /// [`Camera::set_aspect_ratio`] to match the render resolution.
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Scene::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Writes the scene to a file, see [`Scene::to_ron`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    /// Parses a scene from the text of a scene file. Relative paths in the scene are resolved from
+    /// the working directory.
+    pub fn from_ron(text: &str) -> Result<Scene, SceneError> {
+        Scene::parse(text, Path::new(""))
+    }
+
+    fn parse(text: &str, directory: &Path) -> Result<Scene, SceneError> {
+        let descriptor: SceneDescriptor = ron::from_str(text).map_err(|e| SceneError::Parse {
+            line: e.position.line,
+            column: e.position.col,
+            message: e.code.to_string(),
+        })?;
+        Scene::build(&descriptor, directory).map_err(|e| match e {
+            SceneError::UnknownMaterial { name, .. } => SceneError::UnknownMaterial {
+                line: find_line(text, &format!("\"{name}\"")),
+                name,
+            },
+            e => e,
+        })
+    }
+
+    /// Formats the scene as the text of a scene file. Materials used by objects that aren't
+    /// listed in [`Scene::materials`] are given generated names.
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        ron::ser::to_string_pretty(&self.descriptor()?, ron::ser::PrettyConfig::new())
+            .map_err(SceneError::Serialize)
+    }
+
+    /// Builds a scene. Relative paths in the scene are resolved from the working directory.
+    pub fn from_descriptor(descriptor: &SceneDescriptor) -> Result<Scene, SceneError> {
+        Scene::build(descriptor, Path::new(""))
+    }
+
+    fn build(descriptor: &SceneDescriptor, directory: &Path) -> Result<Scene, SceneError> {
+        let materials: BTreeMap<String, Arc<dyn Material>> = descriptor
+            .materials
+            .iter()
+            .map(|(name, material)| (name.clone(), material.build()))
+            .collect();
+
+        let objects = descriptor
+            .objects
+            .iter()
+            .map(|object| {
+                let material =
+                    materials
+                        .get(&object.material)
+                        .ok_or_else(|| SceneError::UnknownMaterial {
+                            name: object.material.clone(),
+                            line: None,
+                        })?;
+                Ok(SdfObject::from_boxed(
+                    object.shape.build(),
+                    material.clone(),
+                ))
+            })
+            .collect::<Result<_, SceneError>>()?;
+
+        let camera = &descriptor.camera;
+        Ok(Scene {
+            camera: Camera::new(
+                camera.position,
+                camera.target,
+                camera.up,
+                camera.vertical_fov.to_radians(),
+                1.0,
+            ),
+            objects,
+            materials,
+            lights: descriptor
+                .lights
+                .iter()
+                .map(LightDescriptor::build)
+                .collect(),
+            background: Background {
+                environment: descriptor.background.environment.build(directory)?,
+                rotation: descriptor.background.rotation.to_radians(),
+                intensity: descriptor.background.intensity,
+            },
+        })
+    }
+
+    pub fn descriptor(&self) -> Result<SceneDescriptor, SceneError> {
+        let mut materials = BTreeMap::new();
+        for (name, material) in &self.materials {
+            let descriptor = material
+                .descriptor()
+                .ok_or_else(|| SceneError::Unsupported(format!("material \"{name}\"")))?;
+            materials.insert(name.clone(), descriptor);
+        }
+
+        let mut objects = Vec::with_capacity(self.objects.len());
+        for (i, object) in self.objects.iter().enumerate() {
+            let shape = object
+                .descriptor()
+                .ok_or_else(|| SceneError::Unsupported(format!("the shape of object {i}")))?;
+            let material = match self
+                .materials
+                .iter()
+                .find(|(_, material)| Arc::ptr_eq(material, object.material()))
+            {
+                Some((name, _)) => name.clone(),
+                None => {
+                    let name = format!("material_{i}");
+                    let descriptor = object.material().descriptor().ok_or_else(|| {
+                        SceneError::Unsupported(format!("the material of object {i}"))
+                    })?;
+                    materials.insert(name.clone(), descriptor);
+                    name
+                }
+            };
+            objects.push(ObjectDescriptor { shape, material });
+        }
+
+        let camera = &self.camera;
+        Ok(SceneDescriptor {
+            camera: CameraDescriptor {
+                position: camera.position,
+                target: camera.target,
+                up: camera.up,
+                vertical_fov: camera.vertical_fov.to_degrees(),
+            },
+            materials,
+            objects,
+            lights: self.lights.iter().map(Light::descriptor).collect(),
+            background: BackgroundDescriptor {
+                environment: self
+                    .background
+                    .environment
+                    .descriptor()
+                    .ok_or_else(|| SceneError::Unsupported("the environment".into()))?,
+                rotation: self.background.rotation.to_degrees(),
+                intensity: self.background.intensity,
+            },
+        })
+    }
+
+    /// Replaces the material called `name`, on every object that uses it. Returns `false` if
+    /// there is no material with that name.
+    pub fn set_material(&mut self, name: &str, material: Arc<dyn Material>) -> bool {
+        let Some(previous) = self.materials.get_mut(name) else {
+            return false;
+        };
+        for object in &mut self.objects {
+            if Arc::ptr_eq(&object.material, previous) {
+                object.material = material.clone();
+            }
+        }
+        *previous = material;
+        true
+    }
+
+    /// A small scene with a few spheres of different materials resting on a ground plane.
+    pub fn demo(aspect_ratio: f32) -> Scene {
+        let mut scene = Scene::from_ron(include_str!("../../../scenes/demo.ron"))
+            .expect("the demo scene is valid");
+        scene.camera.set_aspect_ratio(aspect_ratio);
+        scene
+    }
+}
+
+fn white() -> Vec3 {
+    Vec3::ONE
+}
+
+fn unscaled() -> Vec3 {
+    Vec3::ONE
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn half() -> f32 {
+    0.5
+}
+
+fn three() -> f32 {
+    3.0
+}
+
+/// Returns the 1-based line number of the first occurrence of `pattern` in `text`.
+fn find_line(text: &str, pattern: &str) -> Option<usize> {
+    text.lines()
+        .position(|line| line.contains(pattern))
+        .map(|i| i + 1)
+}
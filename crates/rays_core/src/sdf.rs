@@ -0,0 +1,857 @@
+//! Signed distance functions of primitive shapes, most of them following Inigo Quilez's
+//! [distance functions](https://iquilezles.org/articles/distfunctions/).
+//!
+//! Distances are exact unless noted otherwise, so rays can march the full distance to the surface.
+//! The others are lower bounds of the distance, which take rays more steps to reach the surface.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use glam::{Vec2, Vec3, Vec3A, Vec4, Vec4Swizzles};
+
+use crate::{scene::ShapeDescriptor, BoundingSphere, Sdf};
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec3A};
+
+    use crate::{
+        sdf::{
+            BoxFrame, CappedTorus, Capsule, Cone, Cuboid, Cylinder, Ellipsoid, HexagonalPrism,
+            Octahedron, Plane, RoundedBox, Sphere, Torus,
+        },
+        Sdf,
+    };
+
+    /// Every bounded primitive, centered around the origin.
+    fn bounded_shapes() -> Vec<Box<dyn Sdf>> {
+        vec![
+            Box::new(Sphere::new(Vec3::new(0.1, 0.2, 0.3), 0.8)),
+            Box::new(Cuboid::new(Vec3::ZERO, Vec3::new(0.5, 0.8, 1.1))),
+            Box::new(RoundedBox::new(Vec3::ZERO, Vec3::new(0.5, 0.8, 1.1), 0.2)),
+            Box::new(BoxFrame::new(Vec3::ZERO, Vec3::new(0.5, 0.8, 1.1), 0.1)),
+            Box::new(Torus::new(Vec3::ZERO, 0.8, 0.25)),
+            Box::new(CappedTorus::new(Vec3::ZERO, 2.0, 0.8, 0.25)),
+            Box::new(Capsule::new(
+                Vec3::new(-0.5, -0.2, 0.1),
+                Vec3::ONE * 0.6,
+                0.3,
+            )),
+            Box::new(Cylinder::new(Vec3::ZERO, 0.6, 0.9)),
+            Box::new(Cone::new(Vec3::Y, 0.5, 1.5)),
+            Box::new(Ellipsoid::new(Vec3::ZERO, Vec3::new(0.4, 1.2, 0.8))),
+            Box::new(HexagonalPrism::new(Vec3::ZERO, 0.7, 0.5)),
+            Box::new(Octahedron::new(Vec3::ZERO, 1.1)),
+        ]
+    }
+
+    #[test]
+    fn normals_match_the_distance_gradient() {
+        let mut shapes = bounded_shapes();
+        shapes.push(Box::new(Plane::new(Vec3::new(0.2, 1.0, -0.3), 0.4)));
+        let rng = fastrand::Rng::with_seed(0);
+        let h = 1e-3;
+        for shape in &shapes {
+            for _ in 0..200 {
+                let p = Vec3A::new(rng.f32(), rng.f32(), rng.f32()) * 4.0 - 2.0;
+                let gradient = Vec3A::new(
+                    shape.distance(p + Vec3A::X * h) - shape.distance(p - Vec3A::X * h),
+                    shape.distance(p + Vec3A::Y * h) - shape.distance(p - Vec3A::Y * h),
+                    shape.distance(p + Vec3A::Z * h) - shape.distance(p - Vec3A::Z * h),
+                ) / (2.0 * h);
+                // Skip creases, where the gradient changes abruptly, and bounds, where it's shorter
+                if (gradient.length() - 1.0).abs() > 1e-2 {
+                    continue;
+                }
+                let normal = shape.normal(p);
+                assert!(
+                    (normal - gradient).length() < 1e-2,
+                    "{normal} != {gradient} at {p}"
+                );
+            }
+        }
+        // Distances are exact
+        let cuboid = Cuboid::new(Vec3::ZERO, Vec3::ONE);
+        assert!((cuboid.distance(Vec3A::new(3.0, 3.0, 0.5)) - 8f32.sqrt()).abs() < 1e-5);
+        assert_eq!(cuboid.distance(Vec3A::new(0.0, 0.5, 0.0)), -0.5);
+    }
+
+    #[test]
+    fn zero_angle_cones_are_segments() {
+        let cone = Cone::new(Vec3::Y, 0.0, 1.5);
+        for (p, distance) in [
+            (Vec3A::new(0.0, 0.5, 0.0), 0.0),
+            (Vec3A::new(1.0, 0.5, 0.0), 1.0),
+            (Vec3A::new(0.0, 3.0, 0.0), 2.0),
+            (Vec3A::new(0.0, -2.5, 0.0), 2.0),
+        ] {
+            assert!((cone.distance(p) - distance).abs() < 2e-3, "at {p}");
+        }
+    }
+
+    #[test]
+    fn distances_are_bounded_and_inside_bounds() {
+        let rng = fastrand::Rng::with_seed(0);
+        let random = || Vec3A::new(rng.f32(), rng.f32(), rng.f32()) * 5.0 - 2.5;
+        for shape in bounded_shapes() {
+            let bounds = shape.bounds().unwrap();
+            for _ in 0..2000 {
+                let (p, q) = (random(), random());
+                // Marching by the distance never steps through the surface
+                let (dp, dq) = (shape.distance(p), shape.distance(q));
+                assert!((dp - dq).abs() <= p.distance(q) * 1.001);
+                if dp <= 0.0 {
+                    assert!(p.distance(bounds.center) <= bounds.radius + 1e-4);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Sphere {
+    /// Position and radius packed into a Vec4
+    pos_rad: Vec4,
+}
+impl Sphere {
+    pub fn new(position: Vec3, radius: f32) -> Self {
+        Sphere {
+            pos_rad: position.extend(radius),
+        }
+    }
+}
+impl Sdf for Sphere {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        ray_position.distance(self.pos_rad.xyz().into()) - self.pos_rad.w
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        (ray_position - Vec3A::from(self.pos_rad.xyz())).normalize()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Sphere {
+            center: self.pos_rad.xyz(),
+            radius: self.pos_rad.w,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.pos_rad.xyz().into(),
+            radius: self.pos_rad.w,
+        })
+    }
+}
+
+/// An infinite plane, with everything behind it inside. It can't be bounded, so emissive planes
+/// aren't sampled directly.
+#[derive(Clone)]
+pub struct Plane {
+    /// Normalized direction the plane faces.
+    normal: Vec3A,
+    /// Distance from the origin to the plane, along `normal`.
+    offset: f32,
+}
+impl Plane {
+    pub fn new(normal: Vec3, offset: f32) -> Self {
+        Plane {
+            normal: normal.normalize().into(),
+            offset,
+        }
+    }
+}
+impl Sdf for Plane {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        ray_position.dot(self.normal) - self.offset
+    }
+
+    #[inline(always)]
+    fn normal(&self, _ray_position: Vec3A) -> Vec3A {
+        self.normal
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Plane {
+            normal: self.normal.into(),
+            offset: self.offset,
+        })
+    }
+}
+
+/// An axis-aligned box. Named to avoid confusion with [`Box`].
+#[derive(Clone)]
+pub struct Cuboid {
+    center: Vec3A,
+    /// Distance from the center to the faces, along each axis.
+    half_extents: Vec3A,
+}
+impl Cuboid {
+    pub fn new(center: Vec3, half_extents: Vec3) -> Self {
+        Cuboid {
+            center: center.into(),
+            half_extents: half_extents.abs().into(),
+        }
+    }
+}
+impl Sdf for Cuboid {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        box_distance((ray_position - self.center).abs() - self.half_extents)
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let p = ray_position - self.center;
+        box_gradient(p.abs() - self.half_extents) * p.signum()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Box {
+            center: self.center.into(),
+            half_extents: self.half_extents.into(),
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.half_extents.length(),
+        })
+    }
+}
+
+/// An axis-aligned box with its edges and corners rounded off.
+#[derive(Clone)]
+pub struct RoundedBox {
+    center: Vec3A,
+    /// Distance from the center to the faces, along each axis.
+    half_extents: Vec3A,
+    /// Radius of the rounded edges, at most the smallest half extent.
+    radius: f32,
+}
+impl RoundedBox {
+    pub fn new(center: Vec3, half_extents: Vec3, radius: f32) -> Self {
+        let half_extents = half_extents.abs();
+        RoundedBox {
+            center: center.into(),
+            half_extents: half_extents.into(),
+            radius: radius.clamp(0.0, half_extents.min_element()),
+        }
+    }
+}
+impl Sdf for RoundedBox {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        // A smaller box, grown by the radius
+        let q = (ray_position - self.center).abs() - self.half_extents + self.radius;
+        box_distance(q) - self.radius
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let p = ray_position - self.center;
+        box_gradient(p.abs() - self.half_extents + self.radius) * p.signum()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::RoundedBox {
+            center: self.center.into(),
+            half_extents: self.half_extents.into(),
+            radius: self.radius,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.half_extents.length(),
+        })
+    }
+}
+
+/// The edges of an axis-aligned box, as square beams of width `2 * thickness`.
+#[derive(Clone)]
+pub struct BoxFrame {
+    center: Vec3A,
+    /// Distance from the center to the outside of the frame, along each axis.
+    half_extents: Vec3A,
+    /// Half the width of the beams.
+    thickness: f32,
+}
+impl BoxFrame {
+    pub fn new(center: Vec3, half_extents: Vec3, thickness: f32) -> Self {
+        let half_extents = half_extents.abs();
+        BoxFrame {
+            center: center.into(),
+            half_extents: half_extents.into(),
+            thickness: thickness.clamp(0.0, half_extents.min_element()),
+        }
+    }
+
+    /// The three beams closest to `p`, relative to the center, as the `q` argument of
+    /// [`box_distance`]. Each beam takes the coordinates of `p` folded against the box on its own
+    /// axis, and folded again against the beam's width on the others. The first folding is also
+    /// returned.
+    #[inline(always)]
+    fn beams(&self, p: Vec3A) -> (Vec3A, [Vec3A; 3]) {
+        let outer = p.abs() - self.half_extents;
+        let inner = (outer + self.thickness).abs() - self.thickness;
+        let beams = [
+            Vec3A::new(outer.x, inner.y, inner.z),
+            Vec3A::new(inner.x, outer.y, inner.z),
+            Vec3A::new(inner.x, inner.y, outer.z),
+        ];
+        (outer, beams)
+    }
+}
+impl Sdf for BoxFrame {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        let (_, beams) = self.beams(ray_position - self.center);
+        beams
+            .map(box_distance)
+            .into_iter()
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let p = ray_position - self.center;
+        let (outer, beams) = self.beams(p);
+        let (axis, beam) = beams
+            .into_iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| box_distance(*a).total_cmp(&box_distance(*b)))
+            .expect("there are three beams");
+        // Chain rule through the folds: `inner` flips with the sign of `outer + thickness`
+        let mut chain = (outer + self.thickness).signum();
+        chain[axis] = 1.0;
+        (box_gradient(beam) * chain * p.signum()).normalize()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::BoxFrame {
+            center: self.center.into(),
+            half_extents: self.half_extents.into(),
+            thickness: self.thickness,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.half_extents.length(),
+        })
+    }
+}
+
+/// A ring around the Y axis, with a circular cross section.
+#[derive(Clone)]
+pub struct Torus {
+    center: Vec3A,
+    /// Distance from the center to the middle of the ring.
+    major_radius: f32,
+    /// Radius of the cross section of the ring.
+    minor_radius: f32,
+}
+impl Torus {
+    pub fn new(center: Vec3, major_radius: f32, minor_radius: f32) -> Self {
+        Torus {
+            center: center.into(),
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    /// The point of the circle running through the middle of the ring closest to `p`.
+    #[inline(always)]
+    fn closest_on_ring(&self, p: Vec3A) -> Vec3A {
+        let radial = Vec3A::new(p.x, 0.0, p.z)
+            .try_normalize()
+            .unwrap_or(Vec3A::X);
+        radial * self.major_radius
+    }
+}
+impl Sdf for Torus {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        let p = ray_position - self.center;
+        let q = Vec2::new(Vec2::new(p.x, p.z).length() - self.major_radius, p.y);
+        q.length() - self.minor_radius
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let p = ray_position - self.center;
+        (p - self.closest_on_ring(p)).normalize()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Torus {
+            center: self.center.into(),
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.major_radius + self.minor_radius,
+        })
+    }
+}
+
+/// An arc of a [`Torus`] in the XY plane, symmetric around the +Y axis and spanning `angle` to
+/// each side of it.
+#[derive(Clone)]
+pub struct CappedTorus {
+    center: Vec3A,
+    /// Half the angle covered by the arc, in radians.
+    angle: f32,
+    major_radius: f32,
+    minor_radius: f32,
+}
+impl CappedTorus {
+    pub fn new(center: Vec3, angle: f32, major_radius: f32, minor_radius: f32) -> Self {
+        CappedTorus {
+            center: center.into(),
+            angle: angle.clamp(0.0, PI),
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+impl Sdf for CappedTorus {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        let mut p = ray_position - self.center;
+        p.x = p.x.abs();
+        let (sin, cos) = self.angle.sin_cos();
+        // Distance along the arc, or to the end cap past it
+        let k = if cos * p.x > sin * p.y {
+            p.x * sin + p.y * cos
+        } else {
+            Vec2::new(p.x, p.y).length()
+        };
+        (p.length_squared() + self.major_radius * self.major_radius - 2.0 * self.major_radius * k)
+            .max(0.0)
+            .sqrt()
+            - self.minor_radius
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        numerical_normal(self, ray_position)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::CappedTorus {
+            center: self.center.into(),
+            angle: self.angle.to_degrees(),
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.major_radius + self.minor_radius,
+        })
+    }
+}
+
+/// A cylinder with hemispherical ends, around the segment from `start` to `end`.
+#[derive(Clone)]
+pub struct Capsule {
+    start: Vec3A,
+    end: Vec3A,
+    radius: f32,
+}
+impl Capsule {
+    pub fn new(start: Vec3, end: Vec3, radius: f32) -> Self {
+        Capsule {
+            start: start.into(),
+            end: end.into(),
+            radius,
+        }
+    }
+
+    /// Offset from the point of the segment closest to `p`, to `p`.
+    #[inline(always)]
+    fn offset(&self, p: Vec3A) -> Vec3A {
+        let pa = p - self.start;
+        let ba = self.end - self.start;
+        let h = (pa.dot(ba) / ba.length_squared().max(f32::MIN_POSITIVE)).clamp(0.0, 1.0);
+        pa - ba * h
+    }
+}
+impl Sdf for Capsule {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        self.offset(ray_position).length() - self.radius
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        self.offset(ray_position).normalize()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Capsule {
+            start: self.start.into(),
+            end: self.end.into(),
+            radius: self.radius,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: (self.start + self.end) / 2.0,
+            radius: self.start.distance(self.end) / 2.0 + self.radius,
+        })
+    }
+}
+
+/// A cylinder along the Y axis, with flat ends.
+#[derive(Clone)]
+pub struct Cylinder {
+    center: Vec3A,
+    radius: f32,
+    /// Distance from the center to the ends.
+    half_height: f32,
+}
+impl Cylinder {
+    pub fn new(center: Vec3, radius: f32, half_height: f32) -> Self {
+        Cylinder {
+            center: center.into(),
+            radius,
+            half_height,
+        }
+    }
+
+    /// The position relative to the cylinder, as the distances to its side and to its ends.
+    #[inline(always)]
+    fn folded(&self, p: Vec3A) -> Vec2 {
+        Vec2::new(Vec2::new(p.x, p.z).length(), p.y).abs()
+            - Vec2::new(self.radius, self.half_height)
+    }
+}
+impl Sdf for Cylinder {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        let d = self.folded(ray_position - self.center);
+        d.max_element().min(0.0) + d.max(Vec2::ZERO).length()
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        let p = ray_position - self.center;
+        let d = self.folded(p);
+        let gradient = if d.max_element() > 0.0 {
+            d.max(Vec2::ZERO).normalize()
+        } else if d.x > d.y {
+            Vec2::X
+        } else {
+            Vec2::Y
+        };
+        let radial = Vec3A::new(p.x, 0.0, p.z)
+            .try_normalize()
+            .unwrap_or(Vec3A::X);
+        (radial * gradient.x + Vec3A::Y * gradient.y * p.y.signum()).normalize()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Cylinder {
+            center: self.center.into(),
+            radius: self.radius,
+            half_height: self.half_height,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: Vec2::new(self.radius, self.half_height).length(),
+        })
+    }
+}
+
+/// A cone with its tip on top, opening downwards around the Y axis with a flat base.
+#[derive(Clone)]
+pub struct Cone {
+    tip: Vec3A,
+    /// Angle between the axis and the side, in radians.
+    angle: f32,
+    height: f32,
+}
+impl Cone {
+    pub fn new(tip: Vec3, angle: f32, height: f32) -> Self {
+        Cone {
+            tip: tip.into(),
+            // A cone with no angle has no base to divide the distance by
+            angle: angle.clamp(1e-3, FRAC_PI_2 - 1e-3),
+            height,
+        }
+    }
+
+    #[inline(always)]
+    fn base_radius(&self) -> f32 {
+        self.height * self.angle.tan()
+    }
+}
+impl Sdf for Cone {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        let p = ray_position - self.tip;
+        // In the plane through the axis: the edge of the base, and the position
+        let q = Vec2::new(self.base_radius(), -self.height);
+        let w = Vec2::new(Vec2::new(p.x, p.z).length(), p.y);
+        // Closest points on the side and on the base
+        let a = w - q * (w.dot(q) / q.length_squared()).clamp(0.0, 1.0);
+        let b = w - q * Vec2::new((w.x / q.x).clamp(0.0, 1.0), 1.0);
+        let d = a.length_squared().min(b.length_squared());
+        let s = (-(w.x * q.y - w.y * q.x)).max(-(w.y - q.y));
+        d.sqrt() * s.signum()
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        numerical_normal(self, ray_position)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Cone {
+            tip: self.tip.into(),
+            angle: self.angle.to_degrees(),
+            height: self.height,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.tip - Vec3A::Y * self.height / 2.0,
+            radius: Vec2::new(self.base_radius(), self.height / 2.0).length(),
+        })
+    }
+}
+
+/// An axis-aligned ellipsoid. Its distance is a lower bound, exact only on the surface, so rays
+/// take more steps to reach it the more it is stretched.
+#[derive(Clone)]
+pub struct Ellipsoid {
+    center: Vec3A,
+    /// Radius along each axis.
+    radii: Vec3A,
+}
+impl Ellipsoid {
+    pub fn new(center: Vec3, radii: Vec3) -> Self {
+        Ellipsoid {
+            center: center.into(),
+            radii: radii.abs().into(),
+        }
+    }
+}
+impl Sdf for Ellipsoid {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        // Scaling space by the radii turns the ellipsoid into a unit sphere, and stretches
+        // distances by at most the smallest radius
+        (((ray_position - self.center) / self.radii).length() - 1.0) * self.radii.min_element()
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        ((ray_position - self.center) / (self.radii * self.radii)).normalize()
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Ellipsoid {
+            center: self.center.into(),
+            radii: self.radii.into(),
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.radii.max_element(),
+        })
+    }
+}
+
+/// A prism along the Y axis with a regular hexagon as its cross section.
+#[derive(Clone)]
+pub struct HexagonalPrism {
+    center: Vec3A,
+    /// Distance from the axis to the flat sides.
+    radius: f32,
+    /// Distance from the center to the ends.
+    half_height: f32,
+}
+impl HexagonalPrism {
+    pub fn new(center: Vec3, radius: f32, half_height: f32) -> Self {
+        HexagonalPrism {
+            center: center.into(),
+            radius,
+            half_height,
+        }
+    }
+}
+impl Sdf for HexagonalPrism {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        // -cos(30°), sin(30°) and tan(30°)
+        const K: Vec3 = Vec3::new(-0.866_025_4, 0.5, 0.577_350_26);
+        let p = (ray_position - self.center).abs();
+        // Fold the hexagon in the XZ plane onto a single side
+        let mut xz = Vec2::new(p.x, p.z);
+        xz -= 2.0 * K.truncate().dot(xz).min(0.0) * K.truncate();
+        let edge = K.z * self.radius;
+        let d = Vec2::new(
+            (xz - Vec2::new(xz.x.clamp(-edge, edge), self.radius)).length()
+                * (xz.y - self.radius).signum(),
+            p.y - self.half_height,
+        );
+        d.max_element().min(0.0) + d.max(Vec2::ZERO).length()
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        numerical_normal(self, ray_position)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::HexagonalPrism {
+            center: self.center.into(),
+            radius: self.radius,
+            half_height: self.half_height,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        // The corners of the hexagon are further out than its sides
+        let corner = self.radius / (PI / 6.0).cos();
+        Some(BoundingSphere {
+            center: self.center,
+            radius: Vec2::new(corner, self.half_height).length(),
+        })
+    }
+}
+
+/// A regular octahedron, with its corners on the axes.
+#[derive(Clone)]
+pub struct Octahedron {
+    center: Vec3A,
+    /// Distance from the center to the corners.
+    size: f32,
+}
+impl Octahedron {
+    pub fn new(center: Vec3, size: f32) -> Self {
+        Octahedron {
+            center: center.into(),
+            size,
+        }
+    }
+}
+impl Sdf for Octahedron {
+    #[inline(always)]
+    fn distance(&self, ray_position: Vec3A) -> f32 {
+        let s = self.size;
+        let p = (ray_position - self.center).abs();
+        let m = p.x + p.y + p.z - s;
+        // Rotate the axis the closest point lies away from into X
+        let q = if 3.0 * p.x < m {
+            p
+        } else if 3.0 * p.y < m {
+            Vec3A::new(p.y, p.z, p.x)
+        } else if 3.0 * p.z < m {
+            Vec3A::new(p.z, p.x, p.y)
+        } else {
+            // The closest point is on the face
+            return m * FRAC_1_SQRT_3;
+        };
+        let k = (0.5 * (q.z - q.y + s)).clamp(0.0, s);
+        Vec3A::new(q.x, q.y - s + k, q.z - k).length()
+    }
+
+    #[inline(always)]
+    fn normal(&self, ray_position: Vec3A) -> Vec3A {
+        numerical_normal(self, ray_position)
+    }
+
+    fn descriptor(&self) -> Option<ShapeDescriptor> {
+        Some(ShapeDescriptor::Octahedron {
+            center: self.center.into(),
+            size: self.size,
+        })
+    }
+
+    #[inline(always)]
+    fn bounds(&self) -> Option<BoundingSphere> {
+        Some(BoundingSphere {
+            center: self.center,
+            radius: self.size,
+        })
+    }
+}
+
+/// 1/√3, the distance from the faces of an [`Octahedron`] per unit of `x + y + z`.
+const FRAC_1_SQRT_3: f32 = 0.577_350_26;
+
+/// The normal of `sdf` at `p`, from the gradient of its distance estimated by sampling it at the
+/// corners of a small tetrahedron. For shapes whose gradient is tedious to derive.
+#[inline(always)]
+fn numerical_normal(sdf: &impl Sdf, p: Vec3A) -> Vec3A {
+    const H: f32 = 1e-4;
+    const CORNERS: [Vec3A; 4] = [
+        Vec3A::new(1.0, -1.0, -1.0),
+        Vec3A::new(-1.0, -1.0, 1.0),
+        Vec3A::new(-1.0, 1.0, -1.0),
+        Vec3A::new(1.0, 1.0, 1.0),
+    ];
+    CORNERS
+        .into_iter()
+        .fold(Vec3A::ZERO, |sum, corner| {
+            sum + corner * sdf.distance(p + corner * H)
+        })
+        .normalize_or_zero()
+}
+
+/// Distance to an axis-aligned box, given `q`: the position folded into the positive octant,
+/// minus the half extents of the box.
+#[inline(always)]
+fn box_distance(q: Vec3A) -> f32 {
+    q.max(Vec3A::ZERO).length() + q.max_element().min(0.0)
+}
+
+/// Gradient of [`box_distance`] with respect to `q`.
+#[inline(always)]
+fn box_gradient(q: Vec3A) -> Vec3A {
+    if q.max_element() > 0.0 {
+        // Outside, towards the closest point of the box
+        q.max(Vec3A::ZERO).normalize()
+    } else if q.x >= q.y && q.x >= q.z {
+        // Inside, towards the closest face
+        Vec3A::X
+    } else if q.y >= q.z {
+        Vec3A::Y
+    } else {
+        Vec3A::Z
+    }
+}
@@ -0,0 +1,134 @@
+//! Splitting the image into square tiles, and ordering them for rendering.
+
+use std::f32::consts::PI;
+
+use crate::Pixel;
+
+#[cfg(test)]
+mod tests {
+    use crate::tile::{layout, TileOrder};
+
+    #[test]
+    fn layouts_cover_the_image_once() {
+        let size = [70, 45];
+        for order in TileOrder::ALL {
+            let mut covered = vec![0; (size[0] * size[1]) as usize];
+            for region in layout(size, 16, order) {
+                for y in region.origin[1]..region.origin[1] + region.size[1] {
+                    for x in region.origin[0]..region.origin[0] + region.size[0] {
+                        covered[(x + y * size[0]) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&n| n == 1), "{order:?}");
+        }
+    }
+}
+
+/// A rectangle of pixels, at most [`crate::RenderSettings::tile_size`] on a side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRegion {
+    /// Position of the bottom left pixel of the tile.
+    pub origin: [u32; 2],
+    pub size: [u32; 2],
+}
+
+/// A finished region of the image, sent by [`crate::PathTracer`] once all its pixels are traced.
+pub struct Tile {
+    pub region: TileRegion,
+    /// Pixels of the region row by row, starting with the bottom row.
+    pub pixels: Vec<Pixel>,
+}
+
+/// The order tiles are rendered in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Row by row, from the top of the image.
+    Scanline,
+    /// Outwards from the center of the image.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other.
+    Hilbert,
+}
+impl TileOrder {
+    pub const ALL: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileOrder::Scanline => "Scanline",
+            TileOrder::Spiral => "Spiral",
+            TileOrder::Hilbert => "Hilbert",
+        }
+    }
+}
+
+/// Splits an image of `size` pixels into tiles of `tile_size` pixels, clipped at the edges, and
+/// sorts them in `order`.
+pub fn layout(size: [u32; 2], tile_size: u32, order: TileOrder) -> Vec<TileRegion> {
+    let tile_size = tile_size.max(1);
+    let columns = size[0].div_ceil(tile_size);
+    let rows = size[1].div_ceil(tile_size);
+
+    let mut cells: Vec<[u32; 2]> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| [column, row]))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => cells.sort_by_key(|&[column, row]| (rows - 1 - row, column)),
+        TileOrder::Spiral => {
+            let center = [(columns as f32 - 1.0) / 2.0, (rows as f32 - 1.0) / 2.0];
+            let key = |&[column, row]: &[u32; 2]| {
+                let dx = column as f32 - center[0];
+                let dy = row as f32 - center[1];
+                // Square rings around the center, each walked around by angle
+                let ring = dx.abs().max(dy.abs()).round();
+                let angle = dy.atan2(dx).rem_euclid(2.0 * PI);
+                (ring, angle)
+            };
+            cells.sort_by(|a, b| {
+                let (a, b) = (key(a), key(b));
+                a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+            });
+        }
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            cells.sort_by_key(|&[column, row]| hilbert_index(side, column, row));
+        }
+    }
+
+    cells
+        .into_iter()
+        .map(|[column, row]| {
+            let origin = [column * tile_size, row * tile_size];
+            TileRegion {
+                origin,
+                size: [
+                    tile_size.min(size[0] - origin[0]),
+                    tile_size.min(size[1] - origin[1]),
+                ],
+            }
+        })
+        .collect()
+}
+
+/// Distance along a Hilbert curve filling a `side` x `side` square, where `side` is a power of two.
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
@@ -0,0 +1,232 @@
+//! Display transforms, turning the linear radiance of a render into displayable 8-bit sRGB.
+
+use dyn_clone::{clone_trait_object, DynClone};
+use glam::{Mat3, Vec3};
+
+use crate::color::Color;
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::tonemap::{all, srgb_oetf};
+
+    #[test]
+    fn srgb_oetf_endpoints() {
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        // The linear segment and the power curve meet at the threshold
+        assert!((srgb_oetf(0.0031308) - 0.0031308 * 12.92).abs() < 1e-5);
+    }
+
+    #[test]
+    fn operators_stay_in_display_range() {
+        for tone_mapper in all() {
+            for value in [0.0, 0.18, 1.0, 10.0, 1000.0] {
+                let mapped = tone_mapper.map(Vec3::splat(value));
+                assert!(
+                    mapped.cmpge(Vec3::ZERO).all() && mapped.cmple(Vec3::ONE).all(),
+                    "{} mapped {value} to {mapped}",
+                    tone_mapper.name()
+                );
+            }
+            assert!(tone_mapper.map(Vec3::ZERO).max_element() < 0.01);
+        }
+    }
+}
+
+/// Compresses linear scene radiance into the `[0, 1]` range of a display.
+pub trait ToneMapper: Send + Sync + DynClone {
+    /// Maps exposed, linear scene radiance to linear display values in `[0, 1]`.
+    fn map(&self, color: Vec3) -> Vec3;
+    /// Name shown in user interfaces, and accepted by [`by_name`].
+    fn name(&self) -> &'static str;
+}
+
+clone_trait_object!(ToneMapper);
+
+/// Every built-in tone mapper, with default parameters.
+pub fn all() -> Vec<Box<dyn ToneMapper>> {
+    vec![
+        Box::new(Clamp),
+        Box::new(Reinhard),
+        Box::new(ExtendedReinhard::default()),
+        Box::new(AcesFilmic),
+        Box::new(AgX),
+    ]
+}
+
+/// Finds a built-in tone mapper by name, ignoring case.
+pub fn by_name(name: &str) -> Option<Box<dyn ToneMapper>> {
+    all()
+        .into_iter()
+        .find(|tone_mapper| tone_mapper.name().eq_ignore_ascii_case(name))
+}
+
+/// No tone mapping, values above 1.0 are clipped.
+#[derive(Clone, Debug)]
+pub struct Clamp;
+impl ToneMapper for Clamp {
+    #[inline(always)]
+    fn map(&self, color: Vec3) -> Vec3 {
+        color.clamp(Vec3::ZERO, Vec3::ONE)
+    }
+
+    fn name(&self) -> &'static str {
+        "Clamp"
+    }
+}
+
+/// `c / (1 + c)` applied to each channel. Never reaches white.
+#[derive(Clone, Debug)]
+pub struct Reinhard;
+impl ToneMapper for Reinhard {
+    #[inline(always)]
+    fn map(&self, color: Vec3) -> Vec3 {
+        let color = color.max(Vec3::ZERO);
+        color / (1.0 + color)
+    }
+
+    fn name(&self) -> &'static str {
+        "Reinhard"
+    }
+}
+
+/// Reinhard with a white point: values at or above `white_point` map to 1.0.
+#[derive(Clone, Debug)]
+pub struct ExtendedReinhard {
+    pub white_point: f32,
+}
+impl Default for ExtendedReinhard {
+    fn default() -> Self {
+        Self { white_point: 4.0 }
+    }
+}
+impl ToneMapper for ExtendedReinhard {
+    #[inline(always)]
+    fn map(&self, color: Vec3) -> Vec3 {
+        let color = color.max(Vec3::ZERO);
+        let white_sq = self.white_point * self.white_point;
+        (color * (1.0 + color / white_sq) / (1.0 + color)).min(Vec3::ONE)
+    }
+
+    fn name(&self) -> &'static str {
+        "Extended Reinhard"
+    }
+}
+
+/// Stephen Hill's fit of the ACES reference rendering and output transforms.
+#[derive(Clone, Debug)]
+pub struct AcesFilmic;
+impl ToneMapper for AcesFilmic {
+    #[inline(always)]
+    fn map(&self, color: Vec3) -> Vec3 {
+        // Matrices are written row by row
+        let input = Mat3::from_cols(
+            Vec3::new(0.59719, 0.35458, 0.04823),
+            Vec3::new(0.07600, 0.90834, 0.01566),
+            Vec3::new(0.02840, 0.13383, 0.83777),
+        )
+        .transpose();
+        let output = Mat3::from_cols(
+            Vec3::new(1.60475, -0.53108, -0.07367),
+            Vec3::new(-0.10208, 1.10813, -0.00605),
+            Vec3::new(-0.00327, -0.07276, 1.07602),
+        )
+        .transpose();
+
+        let v = input * color.max(Vec3::ZERO);
+        let v = (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081);
+        (output * v).clamp(Vec3::ZERO, Vec3::ONE)
+    }
+
+    fn name(&self) -> &'static str {
+        "ACES filmic"
+    }
+}
+
+/// Troy Sobotka's AgX, using Benjamin Wrensch's polynomial approximation of the default contrast
+/// curve. Desaturates bright colors towards white instead of skewing their hue.
+#[derive(Clone, Debug)]
+pub struct AgX;
+impl ToneMapper for AgX {
+    #[inline(always)]
+    fn map(&self, color: Vec3) -> Vec3 {
+        let inset = Mat3::from_cols(
+            Vec3::new(0.84247906, 0.04232824, 0.04237565),
+            Vec3::new(0.0784336, 0.87846864, 0.0784336),
+            Vec3::new(0.07922375, 0.07916613, 0.879143),
+        );
+        let outset = Mat3::from_cols(
+            Vec3::new(1.196879, -0.05289685, -0.05297164),
+            Vec3::new(-0.09802088, 1.1519031, -0.09804345),
+            Vec3::new(-0.09902974, -0.09896118, 1.1510737),
+        );
+        const MIN_EV: f32 = -12.47393;
+        const MAX_EV: f32 = 4.026069;
+
+        let v = (inset * color).max(Vec3::splat(1e-10));
+        let v = Vec3::new(v.x.log2(), v.y.log2(), v.z.log2())
+            .clamp(Vec3::splat(MIN_EV), Vec3::splat(MAX_EV));
+        let x = (v - MIN_EV) / (MAX_EV - MIN_EV);
+
+        // Default contrast curve
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        let v = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+            + 0.4298 * x2
+            + 0.1191 * x
+            - 0.00232;
+
+        // Back to linear
+        let v = (outset * v).max(Vec3::ZERO);
+        Vec3::new(v.x.powf(2.2), v.y.powf(2.2), v.z.powf(2.2)).min(Vec3::ONE)
+    }
+
+    fn name(&self) -> &'static str {
+        "AgX"
+    }
+}
+
+/// The sRGB opto-electronic transfer function, encoding a linear value in `[0, 1]`.
+#[inline(always)]
+pub fn srgb_oetf(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts linear radiance to display values: exposure, then tone mapping, then sRGB encoding.
+#[derive(Clone)]
+pub struct DisplayTransform {
+    /// Exposure adjustment in stops (EV). Each stop doubles the brightness.
+    pub exposure: f32,
+    pub tone_mapper: Box<dyn ToneMapper>,
+}
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_mapper: Box::new(AcesFilmic),
+        }
+    }
+}
+impl DisplayTransform {
+    /// Maps a linear color to 8-bit sRGB. Alpha is passed through unchanged.
+    #[inline(always)]
+    pub fn apply(&self, color: Color) -> [u8; 4] {
+        let exposed = color.rgb() * self.exposure.exp2();
+        let mapped = self.tone_mapper.map(exposed);
+        Color::from(
+            Vec3::new(
+                srgb_oetf(mapped.x),
+                srgb_oetf(mapped.y),
+                srgb_oetf(mapped.z),
+            )
+            .extend(color.a()),
+        )
+        .into_bytes()
+    }
+}
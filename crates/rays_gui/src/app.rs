@@ -1,20 +1,24 @@
-use std::sync::Arc;
+use std::path::Path;
 
-use crossbeam_channel::Receiver;
 use eframe::{
     egui::{
         self,
         plot::{self, Plot, PlotImage},
-        CentralPanel, Color32, Context, DragValue, SidePanel,
+        Button, CentralPanel, Color32, ComboBox, Context, DragValue, SidePanel, Slider, Ui, Window,
     },
     emath::{Pos2, Rect},
     epaint::{ColorImage, ImageDelta, TextureHandle},
     App, CreationContext, Frame,
 };
-use glam::Vec3;
 use rays_core::{
-    material::{Lambertian, Metal},
-    Camera, PathTracer, Pixel, Scene, SdfObject, Sphere,
+    framebuffer::Framebuffer,
+    image,
+    job::RenderJob,
+    sampler,
+    scene::{EnvironmentDescriptor, MaterialDescriptor},
+    tile::TileOrder,
+    tonemap::{self, DisplayTransform},
+    PathTracer, RenderSettings, Scene,
 };
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
@@ -24,14 +28,19 @@ pub struct RaysApp {
     // this how you opt-out of serialization of a member
     //#[cfg_attr(feature = "persistence", serde(skip))]
     texture: TextureHandle,
-    buffer: Vec<u8>,
+    framebuffer: Framebuffer,
+    display: DisplayTransform,
+    /// Show the number of samples of each pixel instead of the image.
+    heatmap: bool,
     input_width: u32,
     input_height: u32,
-    samples: usize,
-    max_bounces: u8,
+    settings: RenderSettings,
     grid: bool,
-    receiver: Receiver<Pixel>,
+    job: RenderJob,
     scene: Scene,
+    image_path: String,
+    save_dialog_open: bool,
+    save_status: Option<String>,
 }
 impl RaysApp {
     pub fn new(cc: &CreationContext<'_>) -> Self {
@@ -47,39 +56,28 @@ impl RaysApp {
             egui::TextureFilter::Nearest,
         );
 
-        let matl1 = Arc::new(Lambertian::new([0.99, 0.1, 0.1, 1.0].into()));
-        let matl2 = Arc::new(Lambertian::new([0.1, 0.9, 0.2, 1.0].into()));
-        let matl3 = Arc::new(Metal::new([0.1, 0.1, 0.9, 1.0].into()));
-        let matl4 = Arc::new(Metal::new([0.3, 0.3, 0.3, 1.0].into()));
-
-        let scene = Scene {
-            camera: Camera::from_aspect_ratio(input_width as f32 / input_height as f32),
-            objects: vec![
-                SdfObject::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5), matl1),
-                SdfObject::new(Sphere::new(Vec3::new(1.0, 0.0, -1.0), 0.5), matl3),
-                SdfObject::new(Sphere::new(Vec3::new(-1.0, 0.0, -1.0), 0.5), matl4),
-                SdfObject::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0), matl2),
-            ],
-            materials: vec![],
-        };
+        let scene = Scene::demo(input_width as f32 / input_height as f32);
 
-        let samples = 32;
-        let max_bounces = 16;
+        let settings = RenderSettings {
+            progressive: true,
+            ..Default::default()
+        };
 
         RaysApp {
             texture,
-            buffer: vec![0; (input_width * input_height * 4) as usize],
+            framebuffer: Framebuffer::new([input_width, input_height]),
+            display: DisplayTransform::default(),
+            heatmap: false,
             grid: true,
-            receiver: PathTracer::build([input_width, input_height]).run(
-                scene.clone(),
-                samples,
-                max_bounces,
-            ),
+            job: PathTracer::build([input_width, input_height])
+                .run(scene.clone(), settings.clone()),
             scene,
             input_width,
             input_height,
-            samples,
-            max_bounces,
+            settings,
+            image_path: "render.png".into(),
+            save_dialog_open: false,
+            save_status: None,
         }
     }
 }
@@ -94,17 +92,22 @@ impl App for RaysApp {
     fn update(&mut self, context: &Context, _frame: &mut Frame) {
         let RaysApp {
             texture,
-            buffer,
+            framebuffer,
+            display,
+            heatmap,
             input_width,
             input_height,
-            samples,
-            max_bounces,
+            settings,
             grid,
-            receiver,
+            job,
             scene,
+            image_path,
+            save_dialog_open,
+            save_status,
         } = self;
 
-        update_texture(texture, buffer, receiver, context);
+        update_texture(texture, framebuffer, job, display, *heatmap, false, context);
+        let mut display_changed = false;
 
         // Build UI
         context.set_debug_on_hover(cfg!(debug_assertions));
@@ -133,37 +136,180 @@ impl App for RaysApp {
                 });
                 ui.collapsing("Quality", |ui| {
                     ui.horizontal(|ui| {
-                        ui.label("Max bounces:");
-                        ui.add(
-                            DragValue::new(max_bounces)
+                        let mut limited = settings.max_bounces.is_some();
+                        ui.checkbox(&mut limited, "Max bounces:");
+                        let mut max_bounces = settings.max_bounces.unwrap_or(16);
+                        ui.add_enabled(
+                            limited,
+                            DragValue::new(&mut max_bounces)
                                 .speed(1.0)
                                 .fixed_decimals(0)
-                                .clamp_range(1..=255usize),
+                                .clamp_range(1..=10_000usize),
                         );
+                        settings.max_bounces = limited.then_some(max_bounces);
                     });
                     ui.horizontal(|ui| {
                         ui.label("Samples:");
                         ui.add(
-                            DragValue::new(samples)
+                            DragValue::new(&mut settings.samples)
                                 .speed(1.0)
                                 .fixed_decimals(0)
                                 .clamp_range(1..=100_000usize),
                         );
                     });
+                    ui.checkbox(&mut settings.progressive, "Progressive");
+                    ComboBox::from_label("Sampler")
+                        .selected_text(settings.sampler.name())
+                        .show_ui(ui, |ui| {
+                            for sampler in sampler::all() {
+                                let selected = sampler.name() == settings.sampler.name();
+                                if ui.selectable_label(selected, sampler.name()).clicked() {
+                                    settings.sampler = sampler;
+                                }
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        let mut adaptive = settings.adaptive_threshold.is_some();
+                        ui.checkbox(&mut adaptive, "Adaptive, max error:");
+                        let mut threshold = settings.adaptive_threshold.unwrap_or(0.05);
+                        ui.add_enabled(
+                            adaptive,
+                            DragValue::new(&mut threshold)
+                                .speed(0.001)
+                                .clamp_range(0.001..=1.0),
+                        );
+                        settings.adaptive_threshold = adaptive.then_some(threshold);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Seed:");
+                        ui.add(DragValue::new(&mut settings.seed).speed(1.0));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Tile size:");
+                        ui.add(
+                            DragValue::new(&mut settings.tile_size)
+                                .speed(1.0)
+                                .fixed_decimals(0)
+                                .clamp_range(1..=512usize),
+                        );
+                    });
+                    ComboBox::from_label("Tile order")
+                        .selected_text(settings.tile_order.name())
+                        .show_ui(ui, |ui| {
+                            for order in TileOrder::ALL {
+                                ui.selectable_value(&mut settings.tile_order, order, order.name());
+                            }
+                        });
+                });
+
+                ui.collapsing("Materials", |ui| {
+                    // Materials are edited through their descriptors, and replaced in the scene
+                    // when changed. Edits are picked up by the next render.
+                    let mut edited = Vec::new();
+                    for (name, material) in &scene.materials {
+                        let Some(mut descriptor) = material.descriptor() else {
+                            continue;
+                        };
+                        ui.label(name.as_str());
+                        if ui
+                            .push_id(name, |ui| material_editor(ui, &mut descriptor))
+                            .inner
+                        {
+                            edited.push((name.clone(), descriptor));
+                        }
+                        ui.separator();
+                    }
+                    for (name, descriptor) in edited {
+                        scene.set_material(&name, descriptor.build());
+                    }
+                });
+
+                ui.collapsing("Environment", |ui| {
+                    let background = &mut scene.background;
+                    if let Some(EnvironmentDescriptor::Sky {
+                        mut elevation,
+                        mut azimuth,
+                        mut turbidity,
+                    }) = background.environment.descriptor()
+                    {
+                        let mut changed = false;
+                        for (value, range, text) in [
+                            (&mut elevation, 0.0..=90.0, "Sun elevation (°)"),
+                            (&mut azimuth, -180.0..=180.0, "Sun azimuth (°)"),
+                            (&mut turbidity, 1.7..=10.0, "Turbidity"),
+                        ] {
+                            changed |= ui.add(Slider::new(value, range).text(text)).changed();
+                        }
+                        if changed {
+                            let sky = EnvironmentDescriptor::Sky {
+                                elevation,
+                                azimuth,
+                                turbidity,
+                            };
+                            if let Ok(environment) = sky.build(Path::new("")) {
+                                background.environment = environment;
+                            }
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Rotation (°):");
+                        let mut degrees = background.rotation.to_degrees();
+                        if ui
+                            .add(
+                                DragValue::new(&mut degrees)
+                                    .speed(1.0)
+                                    .clamp_range(-180.0..=180.0),
+                            )
+                            .changed()
+                        {
+                            background.rotation = degrees.to_radians();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Intensity:");
+                        ui.add(
+                            DragValue::new(&mut background.intensity)
+                                .speed(0.05)
+                                .clamp_range(0.0..=1000.0),
+                        );
+                    });
+                });
+
+                ui.collapsing("Display", |ui| {
+                    ComboBox::from_label("Tone mapping")
+                        .selected_text(display.tone_mapper.name())
+                        .show_ui(ui, |ui| {
+                            for tone_mapper in tonemap::all() {
+                                let selected = tone_mapper.name() == display.tone_mapper.name();
+                                if ui.selectable_label(selected, tone_mapper.name()).clicked() {
+                                    display.tone_mapper = tone_mapper;
+                                    display_changed = true;
+                                }
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        ui.label("Exposure (EV):");
+                        display_changed |= ui
+                            .add(
+                                DragValue::new(&mut display.exposure)
+                                    .speed(0.05)
+                                    .clamp_range(-16.0..=16.0),
+                            )
+                            .changed();
+                    });
+                    display_changed |= ui.checkbox(heatmap, "Sample heatmap").changed();
                 });
 
                 ui.add_space(10.0);
                 ui.checkbox(grid, "Grid");
 
                 if ui.button("Render").clicked() {
-                    buffer.clear();
-                    scene.camera =
-                        Camera::from_aspect_ratio(*input_width as f32 / *input_height as f32);
-                    *receiver = PathTracer::build([*input_width, *input_height]).run(
-                        scene.to_owned(),
-                        *samples,
-                        *max_bounces,
-                    );
+                    job.cancel();
+                    scene
+                        .camera
+                        .set_aspect_ratio(*input_width as f32 / *input_height as f32);
+                    *job = PathTracer::build([*input_width, *input_height])
+                        .run(scene.to_owned(), settings.clone());
                     *texture = context.load_texture(
                         "render area",
                         ColorImage::new(
@@ -172,11 +318,46 @@ impl App for RaysApp {
                         ),
                         egui::TextureFilter::Nearest,
                     );
-                    *buffer = vec![0; (*input_width * *input_height * 4) as usize];
+                    *framebuffer = Framebuffer::new([*input_width, *input_height]);
                 };
+                if ui
+                    .add_enabled(job.is_finished(), Button::new("Save image…"))
+                    .clicked()
+                {
+                    *save_dialog_open = true;
+                }
+                if let Some(status) = save_status {
+                    ui.label(status.as_str());
+                }
             });
         });
 
+        if display_changed {
+            update_texture(texture, framebuffer, job, display, *heatmap, true, context);
+        }
+
+        let mut saved = false;
+        Window::new("Save image")
+            .open(save_dialog_open)
+            .collapsible(false)
+            .resizable(false)
+            .show(context, |ui| {
+                ui.label("File name (.png, .ppm or .pfm):");
+                ui.text_edit_singleline(image_path);
+                if ui.button("Save").clicked() {
+                    *save_status = Some(
+                        match image::save(image_path.as_str(), framebuffer, display) {
+                            Ok(()) => format!("Saved {image_path}"),
+                            Err(e) => format!("Could not save {image_path}: {e}"),
+                        },
+                    );
+                    saved = true;
+                }
+            });
+        if saved {
+            *save_dialog_open = false;
+        }
+
         CentralPanel::default()
             .frame(egui::Frame {
                 fill: context.style().visuals.extreme_bg_color,
@@ -204,27 +385,126 @@ impl App for RaysApp {
     }
 }
 
+/// Shows the parameters of a material. Returns `true` if any of them were changed.
+fn material_editor(ui: &mut Ui, descriptor: &mut MaterialDescriptor) -> bool {
+    let mut changed = false;
+    match descriptor {
+        MaterialDescriptor::Lambertian { albedo } => {
+            ui.horizontal(|ui| {
+                ui.label("Albedo:");
+                changed |= ui.color_edit_button_rgb(albedo.as_mut()).changed();
+            });
+        }
+        MaterialDescriptor::Metal { albedo, roughness } => {
+            ui.horizontal(|ui| {
+                ui.label("Albedo:");
+                changed |= ui.color_edit_button_rgb(albedo.as_mut()).changed();
+            });
+            changed |= ui
+                .add(Slider::new(roughness, 0.0..=1.0).text("Roughness"))
+                .changed();
+        }
+        MaterialDescriptor::Dielectric { ior, tint } => {
+            ui.horizontal(|ui| {
+                ui.label("Tint:");
+                changed |= ui.color_edit_button_rgb(tint.as_mut()).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("IOR:");
+                changed |= ui
+                    .add(DragValue::new(ior).speed(0.01).clamp_range(1.0..=3.0))
+                    .changed();
+            });
+        }
+        MaterialDescriptor::Principled {
+            base_color,
+            metallic,
+            roughness,
+            specular,
+            clearcoat,
+        } => {
+            ui.horizontal(|ui| {
+                ui.label("Base color:");
+                changed |= ui.color_edit_button_rgb(base_color.as_mut()).changed();
+            });
+            for (value, text) in [
+                (metallic, "Metallic"),
+                (roughness, "Roughness"),
+                (specular, "Specular"),
+                (clearcoat, "Clearcoat"),
+            ] {
+                changed |= ui.add(Slider::new(value, 0.0..=1.0).text(text)).changed();
+            }
+        }
+        MaterialDescriptor::Emissive { color, intensity } => {
+            ui.horizontal(|ui| {
+                ui.label("Color:");
+                changed |= ui.color_edit_button_rgb(color.as_mut()).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Intensity:");
+                changed |= ui
+                    .add(
+                        DragValue::new(intensity)
+                            .speed(0.1)
+                            .clamp_range(0.0..=1000.0),
+                    )
+                    .changed();
+            });
+        }
+    }
+    changed
+}
+
+/// Adds finished tiles to the framebuffer, and uploads the regions they cover to the texture.
+/// `force` re-uploads the whole image, e.g. when the display transform changed. The `heatmap`
+/// shows sample counts, which are scaled to the whole image, so it's always uploaded in full.
 fn update_texture(
-    texture: &mut TextureHandle,
-    buffer: &mut [u8],
-    receiver: &mut Receiver<Pixel>,
+    texture: &TextureHandle,
+    framebuffer: &mut Framebuffer,
+    job: &RenderJob,
+    display: &DisplayTransform,
+    heatmap: bool,
+    force: bool,
     ctx: &egui::Context,
 ) {
-    let width = texture.size()[0];
-    let height = texture.size()[1];
-    let updated = !receiver.is_empty();
-    for pixel in receiver.try_iter() {
-        let index = pixel.position[0] as usize * 4 + pixel.position[1] as usize * width * 4;
-        buffer[index..(4 + index)].copy_from_slice(&pixel.color);
+    let mut updated = force;
+    let mut full = force;
+    for tile in job.receiver().try_iter() {
+        framebuffer.add(&tile);
+        updated = true;
+        full |= heatmap;
+        if full {
+            continue;
+        }
+        let region = tile.region;
+        let image = ColorImage::from_rgba_unmultiplied(
+            [region.size[0] as usize, region.size[1] as usize],
+            &framebuffer.region_rgba8(region, display),
+        );
+        ctx.tex_manager().write().set(
+            texture.id(),
+            ImageDelta::partial(
+                [region.origin[0] as usize, region.origin[1] as usize],
+                image,
+                egui::TextureFilter::Nearest,
+            ),
+        );
     }
-    if updated {
-        let image = ColorImage::from_rgba_unmultiplied([width, height], buffer);
-        // TODO: only need to update a section of the texture as data is received. Should probably
-        // change from updating per-pixel, to updating fixed-size chunks of the image as well.
+    if full {
+        let [width, height] = framebuffer.size();
+        let rgba = if heatmap {
+            framebuffer.heatmap_rgba8()
+        } else {
+            framebuffer.to_rgba8(display)
+        };
+        let image = ColorImage::from_rgba_unmultiplied([width as usize, height as usize], &rgba);
         ctx.tex_manager().write().set(
             texture.id(),
             ImageDelta::full(image, egui::TextureFilter::Nearest),
         );
+    }
+    if updated {
         ctx.request_repaint();
     }
 }